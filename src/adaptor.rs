@@ -1,7 +1,17 @@
-use crate::utils;
+use crate::config::{HIGHLIGHT_CLASS_PREFIX, SOURCE_FILE_EXT};
+use crate::media::Image;
+use crate::{utils, Post};
 
 use pulldown_cmark as md;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use syntect::html::{ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+pub const HIGHLIGHT_CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed {
+    prefix: HIGHLIGHT_CLASS_PREFIX,
+};
 
 pub trait AdaptorExt<'a>
 where
@@ -14,6 +24,41 @@ where
             generated_ids: HashSet::new(),
         }
     }
+
+    fn rewrite_source_links<'s>(
+        self,
+        root: &'s Path,
+        post: &'s Post,
+        posts: &'s [Post],
+    ) -> RewriteSourceLinks<'s, Self> {
+        RewriteSourceLinks {
+            iter: self,
+            root,
+            post,
+            posts,
+        }
+    }
+
+    fn highlight_code(self, syntaxes: &SyntaxSet) -> HighlightCode<'_, Self> {
+        HighlightCode {
+            iter: self,
+            syntaxes,
+        }
+    }
+
+    fn responsive_images<'s>(
+        self,
+        uri: &'s str,
+        images: &'s HashMap<String, Image>,
+        widths: &'s [u32],
+    ) -> ResponsiveImages<'s, Self> {
+        ResponsiveImages {
+            iter: self,
+            uri,
+            images,
+            widths,
+        }
+    }
 }
 
 impl<'a, I> AdaptorExt<'a> for I where I: Iterator<Item = md::Event<'a>> {}
@@ -27,6 +72,16 @@ where
     generated_ids: HashSet<String>,
 }
 
+impl<'a, I> HyperlinkHeadings<'a, I>
+where
+    I: Iterator<Item = md::Event<'a>>,
+{
+    /// The heading IDs generated so far.
+    pub fn generated_ids(&self) -> &HashSet<String> {
+        &self.generated_ids
+    }
+}
+
 impl<'a, I> Iterator for HyperlinkHeadings<'a, I>
 where
     I: Iterator<Item = md::Event<'a>>,
@@ -69,3 +124,219 @@ where
         }
     }
 }
+
+pub struct RewriteSourceLinks<'s, I> {
+    iter: I,
+    root: &'s Path,
+    post: &'s Post,
+    posts: &'s [Post],
+}
+
+impl<'a, 's, I> Iterator for RewriteSourceLinks<'s, I>
+where
+    I: Iterator<Item = md::Event<'a>>,
+{
+    type Item = md::Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.iter.next() {
+            Some(md::Event::Start(md::Tag::Link(link_type, dest, title))) => {
+                let dest = self.rewrite(dest);
+                Some(md::Event::Start(md::Tag::Link(link_type, dest, title)))
+            }
+            Some(md::Event::Start(md::Tag::Image(link_type, dest, title))) => {
+                let dest = self.rewrite(dest);
+                Some(md::Event::Start(md::Tag::Image(link_type, dest, title)))
+            }
+            item => item,
+        }
+    }
+}
+
+impl<'s, I> RewriteSourceLinks<'s, I> {
+    /// Point links to Markdown sources to their generated page, and make relative links which
+    /// were written relative to the source file work from the generated page (which may differ).
+    fn rewrite<'a>(&self, dest: md::CowStr<'a>) -> md::CowStr<'a> {
+        let (path, suffix) = utils::split_uri_suffix(&dest);
+        if let Some(target) = find_linked_post(self.root, self.post, self.posts, &dest) {
+            let mut uri = if path.starts_with('/') {
+                target.uri.clone()
+            } else {
+                utils::get_relative_uri(&self.post.uri, &target.uri)
+            };
+            uri.push_str(suffix);
+            return uri.into();
+        }
+
+        let source_uri = utils::path_to_uri(self.root, &self.post.path);
+        if path.is_empty()
+            || path.starts_with('/')
+            || utils::is_external_uri(&dest)
+            || utils::uri_dir(&source_uri) == utils::uri_dir(&self.post.uri)
+        {
+            return dest;
+        }
+
+        let mut uri = utils::get_relative_uri(&self.post.uri, &utils::join_uri(&source_uri, path));
+        uri.push_str(suffix);
+        uri.into()
+    }
+}
+
+/// Find the post that a link to a Markdown source file found in `post` refers to, if any.
+pub fn find_linked_post<'p>(
+    root: &Path,
+    post: &Post,
+    posts: &'p [Post],
+    dest: &str,
+) -> Option<&'p Post> {
+    let (path, _) = utils::split_uri_suffix(dest);
+    if !utils::has_extension(Path::new(path), SOURCE_FILE_EXT) {
+        return None;
+    }
+    let source = utils::resolve_uri(root, &post.path, dest)?;
+    posts.iter().find(|p| p.path == source)
+}
+
+pub struct HighlightCode<'s, I> {
+    iter: I,
+    syntaxes: &'s SyntaxSet,
+}
+
+impl<'a, 's, I> Iterator for HighlightCode<'s, I>
+where
+    I: Iterator<Item = md::Event<'a>>,
+{
+    type Item = md::Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let lang = match self.iter.next() {
+            Some(md::Event::Start(md::Tag::CodeBlock(md::CodeBlockKind::Fenced(lang)))) => lang,
+            item => return item,
+        };
+
+        // Info strings may carry extra attributes after the language (e.g. "rust,ignore").
+        let token = lang
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .next()
+            .unwrap_or("");
+
+        let syntax = match self.syntaxes.find_syntax_by_token(token) {
+            Some(syntax) => syntax,
+            None => {
+                return Some(md::Event::Start(md::Tag::CodeBlock(
+                    md::CodeBlockKind::Fenced(lang),
+                )))
+            }
+        };
+
+        let mut code = String::new();
+        for event in &mut self.iter {
+            match event {
+                md::Event::Text(text) => code.push_str(&text),
+                md::Event::End(md::Tag::CodeBlock(_)) => break,
+                _ => {}
+            }
+        }
+
+        let mut generator = ClassedHTMLGenerator::new_with_class_style(
+            syntax,
+            self.syntaxes,
+            HIGHLIGHT_CLASS_STYLE,
+        );
+        for line in LinesWithEndings::from(&code) {
+            if let Err(e) = generator.parse_html_for_line_which_includes_newline(line) {
+                eprintln!("note: failed to highlight {} code: {}", token, e);
+            }
+        }
+
+        // The theme's background and foreground colors are applied to the "code" class.
+        let mut html = format!(
+            "<pre class=\"{}code\"><code class=\"language-",
+            HIGHLIGHT_CLASS_PREFIX
+        );
+        md::escape::escape_html(&mut html, token).unwrap();
+        html.push_str("\">");
+        html.push_str(&generator.finalize());
+        html.push_str("</code></pre>\n");
+        Some(md::Event::Html(html.into()))
+    }
+}
+
+pub struct ResponsiveImages<'s, I> {
+    iter: I,
+    uri: &'s str,
+    images: &'s HashMap<String, Image>,
+    widths: &'s [u32],
+}
+
+impl<'a, 's, I> Iterator for ResponsiveImages<'s, I>
+where
+    I: Iterator<Item = md::Event<'a>>,
+{
+    type Item = md::Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (link_type, dest, title) = match self.iter.next() {
+            Some(md::Event::Start(md::Tag::Image(link_type, dest, title))) => {
+                (link_type, dest, title)
+            }
+            item => return item,
+        };
+
+        let (dest_path, _) = utils::split_uri_suffix(&dest);
+        let image = match Some(dest_path)
+            .filter(|path| !path.is_empty() && !utils::is_external_uri(&dest))
+            .and_then(|path| self.images.get(&utils::join_uri(self.uri, path)))
+        {
+            Some(image) => image,
+            None => return Some(md::Event::Start(md::Tag::Image(link_type, dest, title))),
+        };
+
+        // Alt text is made up of the text of every inner event, as the HTML renderer does.
+        let mut alt = String::new();
+        let mut nest = 0;
+        for event in &mut self.iter {
+            match event {
+                md::Event::Start(md::Tag::Image(..)) => nest += 1,
+                md::Event::End(md::Tag::Image(..)) if nest == 0 => break,
+                md::Event::End(md::Tag::Image(..)) => nest -= 1,
+                md::Event::Text(text) | md::Event::Code(text) => alt.push_str(&text),
+                md::Event::SoftBreak | md::Event::HardBreak => alt.push(' '),
+                _ => {}
+            }
+        }
+
+        let mut html = String::from("<img src=\"");
+        md::escape::escape_href(&mut html, &dest).unwrap();
+        html.push('"');
+
+        let variants = image.variant_widths(self.widths);
+        if !variants.is_empty() {
+            html.push_str(" srcset=\"");
+            for width in variants {
+                md::escape::escape_href(&mut html, &utils::variant_name(dest_path, width)).unwrap();
+                html.push_str(&format!(" {}w, ", width));
+            }
+            md::escape::escape_href(&mut html, &dest).unwrap();
+            html.push_str(&format!(
+                " {0}w\" sizes=\"(max-width: {0}px) 100vw, {0}px\"",
+                image.width
+            ));
+        }
+
+        html.push_str(&format!(
+            " width=\"{}\" height=\"{}\" loading=\"lazy\" alt=\"",
+            image.width, image.height
+        ));
+        md::escape::escape_html(&mut html, &alt).unwrap();
+        html.push('"');
+        if !title.is_empty() {
+            html.push_str(" title=\"");
+            md::escape::escape_html(&mut html, &title).unwrap();
+            html.push('"');
+        }
+        html.push_str(" />");
+        Some(md::Event::Html(html.into()))
+    }
+}
@@ -1,33 +1,42 @@
-use crate::config::{Config, Minify, SOURCE_FILE_EXT, STYLE_FILE_EXT};
-use crate::{feed, utils, HtmlTemplate, Post};
+use crate::adaptor::HIGHLIGHT_CLASS_STYLE;
+use crate::config::{
+    Config, Minify, FINGERPRINT_MANIFEST_FILE, HIGHLIGHT_CSS_FILE, INCLUDE_RAW_EXTENSIONS,
+    REDIRECTS_FILE, SITEMAP_FILE, SOURCE_FILE_EXT, STYLE_FILE_EXT,
+};
+use crate::fingerprint::{self, Manifest};
+use crate::media::{self, Image};
+use crate::style::{self, Styles};
+use crate::{feed, redirect, series, sitemap, template, utils, weight, HtmlTemplate, Post};
 
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 pub struct Scan {
     /// Root path of the source directory.
-    root: PathBuf,
+    pub root: PathBuf,
     /// Directories to create in the destination.
     dirs_to_create: Vec<PathBuf>,
     /// Files to copy to the destination without any special treatment.
-    files_to_copy: Vec<PathBuf>,
+    pub files_to_copy: Vec<PathBuf>,
     /// URIs to the CSS files found.
     css_files: Vec<String>,
     /// HTML templates found.
-    html_templates: HashMap<PathBuf, HtmlTemplate>,
+    pub html_templates: HashMap<PathBuf, HtmlTemplate>,
     /// Markdown files to parse and generate HTML from.
-    md_files: Vec<Post>,
-    /// ATOM feeds to fill.
-    atom_files: Vec<feed::Meta>,
+    pub md_files: Vec<Post>,
+    /// Atom, RSS and JSON feeds to fill.
+    pub feed_files: Vec<feed::Meta>,
+    /// Raster images referenced by the markdown files, to generate variants of.
+    images: HashMap<String, Image>,
 }
 
 /// Scan a directory containing a blog made up of markdown files, templates and assets.
 pub fn scan_dir(config: &Config, root: PathBuf) -> io::Result<Scan> {
     let mut dirs_to_create = Vec::new();
     let mut css_files = Vec::new();
-    let mut atom_files = Vec::new();
+    let mut feed_files = Vec::new();
     let mut files_to_copy = Vec::new();
     let mut md_files = Vec::new();
     let mut templates = HashSet::new();
@@ -55,11 +64,22 @@ pub fn scan_dir(config: &Config, root: PathBuf) -> io::Result<Scan> {
                     css_files.push(utils::path_to_uri(&root, &entry.path()));
                 }
 
-                if ext.eq_ignore_ascii_case(&config.feed_ext) {
-                    match feed::load_atom_feed(&entry.path()) {
-                        Ok(atom) => atom_files.push(atom),
+                let feed_format = if ext.eq_ignore_ascii_case(&config.feed_ext) {
+                    Some(feed::Format::Atom)
+                } else if ext.eq_ignore_ascii_case(&config.rss_ext) {
+                    Some(feed::Format::Rss)
+                } else if ext.eq_ignore_ascii_case(&config.json_feed_ext) {
+                    Some(feed::Format::Json)
+                } else {
+                    None
+                };
+
+                if let Some(format) = feed_format {
+                    match feed::load_feed(&entry.path(), format) {
+                        Ok(Some(feed)) => feed_files.push(feed),
+                        Ok(None) => files_to_copy.push(entry.path()),
                         Err(e) => {
-                            eprintln!("note: failed to load atom feed: {}: {:?}", e, entry.path());
+                            eprintln!("note: failed to load feed: {}: {:?}", e, entry.path());
                             files_to_copy.push(entry.path());
                         }
                     }
@@ -78,9 +98,58 @@ pub fn scan_dir(config: &Config, root: PathBuf) -> io::Result<Scan> {
         }
     }
 
+    series::number_parts(&mut md_files);
+
+    // Two posts can only end up in the same place through their slug, permalink or aliases.
+    let mut generated = HashMap::new();
+    for post in md_files.iter() {
+        for dist_path in std::iter::once(&post.dist_path).chain(post.aliases.iter()) {
+            if let Some(other) = generated.insert(dist_path, &post.path) {
+                return Err(io::Error::other(format!(
+                    "posts {:?} and {:?} would both be generated at {:?}",
+                    other, post.path, dist_path
+                )));
+            }
+        }
+    }
+
+    // Links the generated highlight theme before any other CSS so that it can be overriden.
+    if config.highlight.is_some() {
+        let uri = format!("/{}", HIGHLIGHT_CSS_FILE);
+        if css_files.contains(&uri) {
+            eprintln!(
+                "note: {} already exists and will be used instead of the highlight theme",
+                HIGHLIGHT_CSS_FILE
+            );
+        } else {
+            css_files.insert(0, uri);
+        }
+    }
+
+    // Detects the images that need responsive variants.
+    let images = if config.image_widths.is_empty() {
+        HashMap::new()
+    } else {
+        media::scan_images(&root, &md_files)
+    };
+
     // Removes the HTML templates from the files that need copying.
     files_to_copy.retain(|path| !templates.contains(path));
 
+    // Redirect stubs would otherwise overwrite (or be overwritten by) the files copied as-is.
+    for post in md_files.iter() {
+        if let Some(alias) = post
+            .aliases
+            .iter()
+            .find(|alias| files_to_copy.contains(alias))
+        {
+            return Err(io::Error::other(format!(
+                "alias of post {:?} would be generated at {:?}, which is an existing file",
+                post.path, alias
+            )));
+        }
+    }
+
     // Parse templates.
     let html_templates = templates
         .into_iter()
@@ -100,7 +169,8 @@ pub fn scan_dir(config: &Config, root: PathBuf) -> io::Result<Scan> {
         css_files,
         html_templates,
         md_files,
-        atom_files,
+        feed_files,
+        images,
     })
 }
 
@@ -136,22 +206,148 @@ pub fn generate_from_scan(config: &Config, scan: Scan, destination: PathBuf) ->
         }
     }
 
-    // Copies all files that need copying.
-    for file in scan.files_to_copy.iter() {
+    // Copies all files that need copying, leaving CSS and HTML for last so that they can reference fingerprinted
+    // files (with the CSS imported by other CSS first, as its name changes too).
+    let mut manifest = Manifest::default();
+    let (css_to_copy, others_to_copy): (Vec<_>, Vec<_>) = scan
+        .files_to_copy
+        .iter()
+        .partition(|file| utils::has_extension(file, STYLE_FILE_EXT));
+    let is_html = |file: &Path| {
+        INCLUDE_RAW_EXTENSIONS
+            .iter()
+            .any(|ext| utils::has_extension(file, ext))
+    };
+    let (html_to_copy, others_to_copy): (Vec<_>, Vec<_>) =
+        others_to_copy.into_iter().partition(|file| is_html(file));
+    let css_to_copy = if config.fingerprint {
+        fingerprint::import_order(&scan.root, css_to_copy)
+    } else {
+        css_to_copy
+    };
+    for file in others_to_copy
+        .into_iter()
+        .chain(css_to_copy)
+        .chain(html_to_copy)
+    {
         let src = file
             .clone()
             .into_os_string()
             .into_string()
             .expect("bad file path");
-        let dst = utils::replace_root(&source, &destination, &src);
+        let mut dst = utils::replace_root(&source, &destination, &src);
+        let fingerprint = config.fingerprint && fingerprint::should_fingerprint(file);
+
+        let content = if utils::has_extension(file, STYLE_FILE_EXT)
+            && (config.fingerprint || config.minify == Minify::full)
+        {
+            let mut css = fs::read_to_string(file)?;
+            if config.fingerprint {
+                css = manifest.rewrite_file(&utils::path_to_uri(&scan.root, file), &css);
+            }
+            if config.minify == Minify::full {
+                css = style::minify_css(&css);
+            }
+            Some(css.into_bytes())
+        } else if config.fingerprint && is_html(file) {
+            let html = fs::read_to_string(file)?;
+            Some(
+                manifest
+                    .rewrite_file(&utils::path_to_uri(&scan.root, file), &html)
+                    .into_bytes(),
+            )
+        } else if fingerprint {
+            Some(fs::read(file)?)
+        } else {
+            None
+        };
+
+        if fingerprint {
+            let content = content.as_ref().unwrap();
+            dst = utils::fingerprint_name(dst.to_str().expect("bad file path"), content).into();
+            manifest.insert(
+                utils::path_to_uri(&scan.root, file),
+                utils::path_to_uri(Path::new(&destination), &dst),
+            );
+        }
+
         if !dst.is_file() {
-            fs::copy(src, dst)?;
+            match content {
+                Some(content) => fs::write(dst, content)?,
+                None => {
+                    fs::copy(src, dst)?;
+                }
+            }
+        }
+    }
+
+    // Emits the highlight theme unless the source provides its own.
+    if let Some(highlight) = config.highlight.as_ref() {
+        if !scan.root.join(HIGHLIGHT_CSS_FILE).is_file() {
+            match syntect::html::css_for_theme_with_class_style(
+                &highlight.theme,
+                HIGHLIGHT_CLASS_STYLE,
+            ) {
+                Ok(mut css) => {
+                    if config.minify == Minify::full {
+                        css = style::minify_css(&css);
+                    }
+                    let uri = format!("/{}", HIGHLIGHT_CSS_FILE);
+                    let mut dst = PathBuf::from(&destination).join(HIGHLIGHT_CSS_FILE);
+                    if config.fingerprint {
+                        manifest.insert(uri.clone(), utils::fingerprint_name(&uri, css.as_bytes()));
+                        dst = PathBuf::from(&destination).join(&manifest.get(&uri)[1..]);
+                    }
+                    fs::write(dst, css)?;
+                }
+                Err(e) => eprintln!("note: failed to generate highlight theme css: {}", e),
+            }
+        }
+    }
+
+    // Generates the downscaled variants of the images that need them (named after the fingerprinted image).
+    for (uri, image) in scan.images.iter() {
+        let fingerprinted = manifest.get(uri).to_owned();
+        if &fingerprinted != uri {
+            for width in image.variant_widths(&config.image_widths) {
+                manifest.insert(
+                    utils::variant_name(uri, width),
+                    utils::variant_name(&fingerprinted, width),
+                );
+            }
         }
+
+        let src = scan.root.join(&uri[1..]);
+        let dst = PathBuf::from(&destination).join(&fingerprinted[1..]);
+        media::generate_variants(&src, &dst, image, &config.image_widths)?;
     }
 
-    // Generate all feeds.
-    for atom in scan.atom_files.iter() {
-        let src = atom
+    if config.fingerprint {
+        fs::write(
+            PathBuf::from(&destination).join(FINGERPRINT_MANIFEST_FILE),
+            manifest.to_json(),
+        )?;
+    }
+
+    // Loads the CSS that needs bundling or inlining now that all of it exists.
+    let styles = Styles::new(
+        config,
+        scan.css_files.clone(),
+        Path::new(&destination),
+        &manifest,
+    );
+
+    // Generate all feeds, with the same contents as the pages.
+    let render = |md: &Post| {
+        let html = template::render_contents(config, &scan.root, md, &scan.md_files, &scan.images);
+        if config.fingerprint {
+            manifest.rewrite_html(&md.uri, &html)
+        } else {
+            html
+        }
+    };
+    for feed in scan.feed_files.iter() {
+        let src = feed
             .path
             .clone()
             .into_os_string()
@@ -159,19 +355,23 @@ pub fn generate_from_scan(config: &Config, scan: Scan, destination: PathBuf) ->
             .expect("bad file path");
 
         let dst = utils::replace_root(&source, &destination, &src);
-        fs::write(dst, feed::fill_atom_feed(atom, &scan.md_files))?;
+        fs::write(dst, feed::fill_feed(feed, &scan.md_files, render))?;
     }
 
     // Converts every MD file to HTML and places it in the destination.
+    let mut weights = Vec::with_capacity(scan.md_files.len());
     for file in scan.md_files.iter() {
         let src = file
-            .path
+            .dist_path
             .clone()
-            .with_extension(&config.dist_ext)
             .into_os_string()
             .into_string()
             .expect("bad md path");
         let dst = utils::replace_root(&source, &destination, &src);
+        if let Some(parent) = dst.parent() {
+            // Pretty URLs need a directory of their own which doesn't exist in the source.
+            fs::create_dir_all(parent)?;
+        }
 
         let template = file
             .template
@@ -179,7 +379,20 @@ pub fn generate_from_scan(config: &Config, scan: Scan, destination: PathBuf) ->
             .and_then(|t| scan.html_templates.get(t))
             .unwrap_or(&config.template);
 
-        let html = template.apply(&scan.root, file, &scan.md_files, &scan.css_files)?;
+        let html = template.apply(
+            config,
+            &scan.root,
+            file,
+            &scan.md_files,
+            &styles,
+            &scan.images,
+        )?;
+
+        let html = if config.fingerprint {
+            manifest.rewrite_html(&file.uri, &html)
+        } else {
+            html
+        };
 
         let html = match config.minify {
             Minify::no => html,
@@ -201,8 +414,38 @@ pub fn generate_from_scan(config: &Config, scan: Scan, destination: PathBuf) ->
             }
         };
 
-        fs::write(dst, html)?;
+        fs::write(&dst, &html)?;
+        weights.push(weight::measure_page(Path::new(&destination), &dst, &html));
+    }
+
+    // Generates the redirects from the old paths of the posts.
+    for file in scan.md_files.iter() {
+        for alias in file.aliases.iter() {
+            let src = alias.to_str().expect("bad alias path");
+            let dst = utils::replace_root(&source, &destination, src);
+            if let Some(parent) = dst.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(dst, redirect::stub_html(config, &scan.root, file, alias))?;
+        }
+    }
+
+    if config.redirects_file {
+        fs::write(
+            PathBuf::from(&destination).join(REDIRECTS_FILE),
+            redirect::redirect_rules(config, &scan.root, &scan.md_files),
+        )?;
     }
 
+    if let Some(base_url) = config.base_url.as_ref().filter(|_| config.sitemap) {
+        fs::write(
+            PathBuf::from(&destination).join(SITEMAP_FILE),
+            sitemap::generate_sitemap(base_url, &scan.md_files),
+        )?;
+    }
+
+    // Reports the weight of the pages now that all their resources exist.
+    weight::check_budget(config, Path::new(&destination), weights)?;
+
     Ok(())
 }
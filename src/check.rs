@@ -0,0 +1,210 @@
+use crate::blog::Scan;
+use crate::config::{
+    Config, FINGERPRINT_MANIFEST_FILE, HIGHLIGHT_CSS_FILE, META_KEY_TEMPLATE, META_VALUE_SEPARATOR,
+    REDIRECTS_FILE, SITEMAP_FILE,
+};
+use crate::{adaptor, utils, AdaptorExt as _, Post};
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Check that the links, paths and templates used by the posts point to something that exists.
+///
+/// Every broken reference is reported along with the file and line where it was found.
+pub fn check_links(config: &Config, scan: &Scan) -> io::Result<()> {
+    // Every URI that will exist in the destination, along with the heading IDs of posts.
+    let mut targets = HashMap::new();
+    for post in scan.md_files.iter() {
+        let mut headings = Parser::new_ext(&post.markdown, Options::all()).hyperlink_headings();
+        headings.by_ref().for_each(drop);
+        targets.insert(post.uri.clone(), Some(headings.generated_ids().clone()));
+        for alias in post.aliases.iter() {
+            targets.insert(utils::path_to_uri(&scan.root, alias), None);
+        }
+    }
+    for file in scan.files_to_copy.iter() {
+        targets.insert(utils::path_to_uri(&scan.root, file), None);
+    }
+    for feed in scan.feed_files.iter() {
+        targets.insert(utils::path_to_uri(&scan.root, &feed.path), None);
+    }
+    // Files the build writes itself, besides the posts.
+    let generated = [
+        (HIGHLIGHT_CSS_FILE, config.highlight.is_some()),
+        (FINGERPRINT_MANIFEST_FILE, config.fingerprint),
+        (REDIRECTS_FILE, config.redirects_file),
+        (SITEMAP_FILE, config.sitemap && config.base_url.is_some()),
+    ];
+    for (file, _) in generated.iter().filter(|(_, enabled)| *enabled) {
+        targets.insert(format!("/{}", file), None);
+    }
+
+    let mut broken = 0;
+    for post in scan.md_files.iter() {
+        // Links are written relative to the source file, which may not be where the post is generated.
+        let source_uri = utils::path_to_uri(&scan.root, &post.path);
+        for (event, range) in Parser::new_ext(&post.markdown, Options::all()).into_offset_iter() {
+            let dest = match event {
+                Event::Start(Tag::Link(_, dest, _)) | Event::Start(Tag::Image(_, dest, _)) => dest,
+                _ => continue,
+            };
+            if utils::is_external_uri(&dest) {
+                continue;
+            }
+
+            // Files are looked up by their name, so `my%20file.png` has to find `my file.png`.
+            let decoded = utils::percent_decode(&dest);
+            let (path, suffix) = utils::split_uri_suffix(&decoded);
+            let fragment = suffix.find('#').map(|i| &suffix[i + 1..]);
+            let target = if path.is_empty() {
+                targets.get(&post.uri)
+            } else if let Some(linked) =
+                adaptor::find_linked_post(&scan.root, post, &scan.md_files, &decoded)
+            {
+                // Links to the Markdown source are rewritten to point to the generated page.
+                targets.get(&linked.uri)
+            } else {
+                find_target(
+                    &targets,
+                    &config.dist_ext,
+                    &utils::join_uri(&source_uri, path),
+                )
+            };
+
+            let reason = match (target, fragment) {
+                (None, _) => "target does not exist",
+                (Some(Some(ids)), Some(fragment))
+                    if !fragment.is_empty() && !ids.contains(fragment) =>
+                {
+                    "heading does not exist"
+                }
+                _ => continue,
+            };
+
+            let line = post.markdown[..range.start].matches('\n').count() + 1 + post.line_offset;
+            eprintln!(
+                "warning: broken link {:?} ({}): {}:{}",
+                dest.as_ref(),
+                reason,
+                post.path.display(),
+                line
+            );
+            broken += 1;
+        }
+    }
+
+    // Templates are shared, so report each of their broken paths only once.
+    let mut reported = HashSet::new();
+    for post in scan.md_files.iter() {
+        let template = match post.template.as_ref() {
+            Some(path) => match scan.html_templates.get(path) {
+                Some(template) => template,
+                None => {
+                    eprintln!(
+                        "warning: broken template {:?} (could not load it): {}:{}",
+                        path,
+                        post.path.display(),
+                        meta_line(post, META_KEY_TEMPLATE)
+                    );
+                    broken += 1;
+                    continue;
+                }
+            },
+            None => &config.template,
+        };
+
+        for (line, rule, path) in template.referenced_paths() {
+            let target = utils::get_abs_path(&scan.root, &post.path, path);
+            if !target.exists() && reported.insert((post.template.clone(), line, target.clone())) {
+                eprintln!(
+                    "warning: broken {} path {:?} (used by {:?}): {}:{}",
+                    rule,
+                    path,
+                    post.path,
+                    post.template
+                        .as_deref()
+                        .map(Path::display)
+                        .map(|p| p.to_string())
+                        .unwrap_or_else(|| "default template".to_owned()),
+                    line
+                );
+                broken += 1;
+            }
+        }
+    }
+
+    if broken != 0 && config.deny_broken_links {
+        Err(io::Error::other(format!("{} broken link(s)", broken)))
+    } else {
+        Ok(())
+    }
+}
+
+/// Find the target of an URI, which may also point to a directory with an index, with or without a trailing slash.
+///
+/// With pretty URLs, the posts themselves are targets whose URI ends with a slash.
+fn find_target<'a, T>(targets: &'a HashMap<String, T>, dist_ext: &str, uri: &str) -> Option<&'a T> {
+    let index_uris = [format!("index.{}", dist_ext), "index.html".to_owned()];
+    if uri.ends_with('/') {
+        targets.get(uri).or_else(|| {
+            index_uris
+                .iter()
+                .find_map(|index| targets.get(&format!("{}{}", uri, index)))
+        })
+    } else {
+        targets
+            .get(uri)
+            .or_else(|| targets.get(&format!("{}/", uri)))
+            .or_else(|| {
+                index_uris
+                    .iter()
+                    .find_map(|index| targets.get(&format!("{}/{}", uri, index)))
+            })
+    }
+}
+
+/// Find the line where the metadata key is defined in the post's file (or the first line, if it can't be found).
+fn meta_line(post: &Post, key: &str) -> usize {
+    fs::read_to_string(&post.path)
+        .ok()
+        .and_then(|source| {
+            source.lines().position(|line| {
+                // Front matter uses `key: value` (YAML) or `key = value` (TOML, meta block).
+                let mut kv = line.splitn(2, |c| META_VALUE_SEPARATOR.contains(c) || c == ':');
+                kv.next().map(|k| k.trim() == key).unwrap_or(false) && kv.next().is_some()
+            })
+        })
+        .map(|i| i + 1)
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets() {
+        let targets = [
+            "/",
+            "/blog/",
+            "/blog/hello/",
+            "/about/index.html",
+            "/a.html",
+        ]
+        .iter()
+        .map(|uri| (uri.to_string(), ()))
+        .collect::<HashMap<_, _>>();
+
+        assert!(find_target(&targets, "html", "/").is_some());
+        assert!(find_target(&targets, "html", "/blog/").is_some());
+        assert!(find_target(&targets, "html", "/blog/hello").is_some());
+        assert!(find_target(&targets, "html", "/about/").is_some());
+        assert!(find_target(&targets, "html", "/about").is_some());
+        assert!(find_target(&targets, "html", "/a.html").is_some());
+        assert!(find_target(&targets, "html", "/b/").is_none());
+        assert!(find_target(&targets, "html", "/a").is_none());
+    }
+}
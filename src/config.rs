@@ -1,9 +1,13 @@
-use crate::HtmlTemplate;
+use crate::git::GitDates;
+use crate::{utils, HtmlTemplate};
 
+use chrono_tz::Tz;
 use clap::{arg_enum, value_t, App, Arg};
 use std::env;
 use std::io;
 use std::path::PathBuf;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
 
 // Program defaults.
 pub const SOURCE_PATH: &str = "content";
@@ -11,13 +15,29 @@ pub const TARGET_PATH: &str = "dist";
 
 // Source file metadata.
 pub const SOURCE_META_KEY: &str = "meta";
+pub const YAML_FRONT_MATTER_FENCE: &str = "---";
+pub const TOML_FRONT_MATTER_FENCE: &str = "+++";
 pub const DATE_FMT: &str = "%F";
+pub const DATETIME_FMT: &str = "%FT%T";
 pub const META_KEY_TITLE: &str = "title";
 pub const META_KEY_CREATION_DATE: &str = "date";
 pub const META_KEY_MODIFIED_DATE: &str = "updated";
 pub const META_KEY_CATEGORY: &str = "category";
 pub const META_KEY_TAGS: &str = "tags";
 pub const META_KEY_TEMPLATE: &str = "template";
+pub const META_KEY_SLUG: &str = "slug";
+pub const META_KEY_PERMALINK: &str = "permalink";
+pub const META_KEY_ALIASES: &str = "aliases";
+pub const META_KEY_DRAFT: &str = "draft";
+pub const META_KEY_SITEMAP: &str = "sitemap";
+pub const META_KEY_SUMMARY: &str = "summary";
+pub const META_KEY_AUTHOR: &str = "author";
+pub const META_KEY_WORD_COUNT: &str = "word_count";
+pub const META_KEY_READING_TIME: &str = "reading_time";
+pub const META_KEY_SERIES: &str = "series";
+pub const META_KEY_SERIES_ORDER: &str = "series_order";
+pub const META_KEY_SERIES_PART: &str = "series_part";
+pub const META_KEY_SERIES_LENGTH: &str = "series_length";
 pub const META_VALUE_SEPARATOR: &str = "=";
 pub const META_TAG_SEPARATOR: &str = ",";
 
@@ -27,17 +47,39 @@ pub const TEMPLATE_OPEN_MARKER: &str = "<!--P/";
 pub const TEMPLATE_CLOSE_MARKER: &str = "/P-->";
 pub const INCLUDE_RAW_EXTENSIONS: [&str; 4] = ["html", "htm", "xhtml", "xht"];
 pub const DEFAULT_MINIFY_LEVEL: &str = "yes";
+pub const DEFAULT_URL_STYLE: &str = "file";
+pub const DEFAULT_WORDS_PER_MINUTE: &str = "200";
 
 // Blog options.
 pub const SOURCE_FILE_EXT: &str = "md";
+pub const INDEX_FILE_STEM: &str = "index";
 pub const DIST_FILE_EXT: &str = "html";
 pub const STYLE_FILE_EXT: &str = "css";
+pub const CSS_BUNDLE_NAME: &str = "bundle";
+pub const FINGERPRINT_MANIFEST_FILE: &str = "manifest.json";
+pub const REDIRECTS_FILE: &str = "_redirects";
+pub const SITEMAP_FILE: &str = "sitemap.xml";
+pub const FINGERPRINT_EXTENSIONS: [&str; 17] = [
+    "css", "js", "png", "jpg", "jpeg", "gif", "webp", "avif", "svg", "woff", "woff2", "ttf", "otf",
+    "mp3", "ogg", "mp4", "webm",
+];
 pub const FEED_FILE_EXT: &str = "atom";
+pub const RSS_FILE_EXT: &str = "rss";
+pub const JSON_FEED_FILE_EXT: &str = "json";
+pub const RASTER_IMAGE_EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+pub const IMAGE_WIDTH_SEPARATOR: &str = ",";
 
 // Feed defaults.
 pub const FEED_CONTENT_TYPE: &str = "html";
 pub const FEED_REL: &str = "self";
 pub const FEED_TYPE: &str = "application/atom+xml";
+pub const JSON_FEED_VERSION: &str = "https://jsonfeed.org/version/1.1";
+pub const FEED_OPTION_PREFIX: &str = "pagong:";
+pub const JSON_FEED_OPTIONS_KEY: &str = "_pagong";
+
+// Highlight defaults.
+pub const HIGHLIGHT_CSS_FILE: &str = "highlight.css";
+pub const HIGHLIGHT_CLASS_PREFIX: &str = "hl-";
 
 arg_enum! {
     #[derive(PartialEq, Debug)]
@@ -49,12 +91,47 @@ arg_enum! {
     }
 }
 
+arg_enum! {
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    #[allow(non_camel_case_types)]
+    pub enum UrlStyle {
+        file,
+        pretty
+    }
+}
+
+pub struct Highlight {
+    pub syntaxes: SyntaxSet,
+    pub theme: Theme,
+}
+
 pub struct Config {
     pub root: PathBuf,
     pub template: HtmlTemplate,
     pub dist_ext: String,
+    pub url_style: UrlStyle,
     pub feed_ext: String,
+    pub rss_ext: String,
+    pub json_feed_ext: String,
     pub minify: Minify,
+    pub highlight: Option<Highlight>,
+    pub css_bundle: bool,
+    pub css_inline: Option<u64>,
+    pub fingerprint: bool,
+    pub image_widths: Vec<u32>,
+    pub deny_broken_links: bool,
+    pub redirects_file: bool,
+    pub base_url: Option<String>,
+    pub author: Option<String>,
+    pub timezone: Option<Tz>,
+    pub git_dates: Option<GitDates>,
+    pub date_prefix: bool,
+    pub words_per_minute: usize,
+    pub count_code_words: bool,
+    pub sitemap: bool,
+    pub weight_report: bool,
+    pub weight_budget: Option<u64>,
+    pub fail_over_budget: bool,
 }
 
 pub fn parse_cli_args() -> io::Result<Config> {
@@ -76,12 +153,30 @@ pub fn parse_cli_args() -> io::Result<Config> {
             .long("generated-extension")
             .help("Sets the file extension for the converted Markdown files")
             .default_value("html"))
+        .arg(Arg::with_name("url_style")
+            .value_name("STYLE")
+            .short("u")
+            .long("url-style")
+            .help("Configures whether posts are generated as \"post.html\" (file) or \"post/index.html\" (pretty, linked to as \"post/\")")
+            .possible_values(&UrlStyle::variants())
+            .case_insensitive(true)
+            .default_value(DEFAULT_URL_STYLE))
         .arg(Arg::with_name("feed_ext")
             .value_name("EXT")
             .short("a")
             .long("feed-extension")
             .help("Sets the file extension used for the Atom feed files")
             .default_value("atom"))
+        .arg(Arg::with_name("rss_ext")
+            .value_name("EXT")
+            .long("rss-extension")
+            .help("Sets the file extension used for the RSS feed files")
+            .default_value("rss"))
+        .arg(Arg::with_name("json_feed_ext")
+            .value_name("EXT")
+            .long("json-feed-extension")
+            .help("Sets the file extension used for the JSON Feed files (other JSON files are copied as-is)")
+            .default_value("json"))
         .arg(Arg::with_name("minify")
             .value_name("MIN")
             .short("m")
@@ -90,6 +185,76 @@ pub fn parse_cli_args() -> io::Result<Config> {
             .possible_values(&Minify::variants())
             .case_insensitive(true)
             .default_value(DEFAULT_MINIFY_LEVEL))
+        .arg(Arg::with_name("css_bundle")
+            .long("css-bundle")
+            .help("Concatenates the CSS files used by each page into a single file"))
+        .arg(Arg::with_name("css_inline")
+            .value_name("SIZE")
+            .long("css-inline")
+            .help("Inlines the CSS files (or bundles) up to this size into a <style> tag (e.g. \"4K\") [default: never inline]"))
+        .arg(Arg::with_name("fingerprint")
+            .short("f")
+            .long("fingerprint")
+            .help("Renames the CSS, images and other assets to include a hash of their content and writes a manifest.json with the new names"))
+        .arg(Arg::with_name("highlight")
+            .value_name("THEME")
+            .short("s")
+            .long("highlight")
+            .help("Highlights fenced code blocks at build time and emits the theme as a CSS file (e.g. \"InspiredGitHub\") [default: no highlighting]"))
+        .arg(Arg::with_name("image_widths")
+            .value_name("WIDTHS")
+            .short("w")
+            .long("image-widths")
+            .help("Sets the comma-separated widths of the downscaled variants generated for the images in the Markdown files (e.g. \"480,960\") [default: no variants]"))
+        .arg(Arg::with_name("deny_broken_links")
+            .long("deny-broken-links")
+            .help("Fails the build if any link, path or template used by the Markdown files is broken instead of only warning"))
+        .arg(Arg::with_name("redirects_file")
+            .long("redirects-file")
+            .help("Writes a \"_redirects\" file with the aliases of every post, for hosts that support it, besides the redirect pages"))
+        .arg(Arg::with_name("base_url")
+            .value_name("URL")
+            .long("base-url")
+            .help("Sets the absolute URL where the site will be hosted (e.g. \"https://example.com\")"))
+        .arg(Arg::with_name("author")
+            .value_name("NAME")
+            .long("author")
+            .help("Sets the author of the posts which don't specify one, as \"Name\" or \"Name <email>\", used in feeds"))
+        .arg(Arg::with_name("timezone")
+            .value_name("TZ")
+            .long("timezone")
+            .help("Sets the time zone of the dates without one (e.g. \"Europe/Madrid\") [default: the system's]"))
+        .arg(Arg::with_name("git_dates")
+            .long("git-dates")
+            .help("Uses the first and last commits of the posts as their dates if their metadata doesn't set them"))
+        .arg(Arg::with_name("date_prefix")
+            .long("date-prefix")
+            .help("Uses the \"YYYY-MM-DD-\" prefix in the file name of the posts as their date, leaving it out of their URL"))
+        .arg(Arg::with_name("words_per_minute")
+            .value_name("N")
+            .long("words-per-minute")
+            .help("Sets how many words are read per minute when estimating the reading time of posts")
+            .default_value(DEFAULT_WORDS_PER_MINUTE))
+        .arg(Arg::with_name("count_code_words")
+            .long("count-code-words")
+            .help("Counts the words inside code blocks towards the word count and reading time of posts"))
+        .arg(Arg::with_name("sitemap")
+            .long("sitemap")
+            .requires("base_url")
+            .help("Writes a \"sitemap.xml\" listing every post to the root of the destination"))
+        .arg(Arg::with_name("weight_report")
+            .short("r")
+            .long("report-weight")
+            .help("Prints the transfer weight of every generated page, including the resources it links to"))
+        .arg(Arg::with_name("weight_budget")
+            .value_name("SIZE")
+            .short("b")
+            .long("weight-budget")
+            .help("Warns about generated pages weighing more than this size (e.g. \"100K\") [default: no budget]"))
+        .arg(Arg::with_name("fail_over_budget")
+            .long("fail-over-budget")
+            .requires("weight_budget")
+            .help("Fails the build if any page is over the weight budget instead of only warning"))
         .arg(Arg::with_name("processor")
             .value_name("PROCESSOR")
             .help("Configures a program that will be used to additionally process the template replacements.")
@@ -124,13 +289,149 @@ pub fn parse_cli_args() -> io::Result<Config> {
         None => FEED_FILE_EXT.to_string(),
     };
 
+    let rss_ext = match config.value_of("rss_ext") {
+        Some(ext) => ext.to_string(),
+        None => RSS_FILE_EXT.to_string(),
+    };
+
+    let json_feed_ext = match config.value_of("json_feed_ext") {
+        Some(ext) => ext.to_string(),
+        None => JSON_FEED_FILE_EXT.to_string(),
+    };
+
+    let url_style = value_t!(config, "url_style", UrlStyle).unwrap_or_else(|e| e.exit());
+
     let minify = value_t!(config, "minify", Minify).unwrap_or_else(|e| e.exit());
 
+    let highlight = match config.value_of("highlight") {
+        Some(name) => {
+            let mut themes = ThemeSet::load_defaults().themes;
+            let theme = match themes.remove(name) {
+                Some(theme) => theme,
+                None => {
+                    let mut names = themes.keys().cloned().collect::<Vec<_>>();
+                    names.sort();
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "unknown highlight theme {:?}, expected one of: {}",
+                            name,
+                            names.join(", ")
+                        ),
+                    ));
+                }
+            };
+            Some(Highlight {
+                syntaxes: SyntaxSet::load_defaults_newlines(),
+                theme,
+            })
+        }
+        None => None,
+    };
+
+    let image_widths = match config.value_of("image_widths") {
+        Some(widths) => widths
+            .split(IMAGE_WIDTH_SEPARATOR)
+            .map(|w| {
+                w.trim().parse().map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("image width {:?} is not a number: {}", w, e),
+                    )
+                })
+            })
+            .collect::<io::Result<_>>()?,
+        None => Vec::new(),
+    };
+
+    let css_inline = match config.value_of("css_inline") {
+        Some(size) => Some(utils::parse_size(size).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("css inline threshold {:?} is not a valid size", size),
+            )
+        })?),
+        None => None,
+    };
+
+    let weight_budget = match config.value_of("weight_budget") {
+        Some(size) => Some(utils::parse_size(size).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("weight budget {:?} is not a valid size", size),
+            )
+        })?),
+        None => None,
+    };
+
+    let base_url = match config.value_of("base_url") {
+        Some(url) if url.starts_with("http://") || url.starts_with("https://") => {
+            Some(url.trim_end_matches('/').to_owned())
+        }
+        Some(url) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("base url {:?} must start with http:// or https://", url),
+            ))
+        }
+        None => None,
+    };
+
+    let timezone = match config.value_of("timezone") {
+        Some(name) => Some(name.parse::<Tz>().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("time zone {:?} is not known", name),
+            )
+        })?),
+        None => None,
+    };
+
+    let git_dates = if config.is_present("git_dates") {
+        Some(GitDates::load(&root)?)
+    } else {
+        None
+    };
+
+    let words_per_minute = match config.value_of("words_per_minute").unwrap().parse() {
+        Ok(n) if n != 0 => n,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "words per minute {:?} is not a positive number",
+                    config.value_of("words_per_minute").unwrap()
+                ),
+            ))
+        }
+    };
+
     Ok(Config {
         root,
         template,
         dist_ext,
+        url_style,
         feed_ext,
+        rss_ext,
+        json_feed_ext,
         minify,
+        highlight,
+        css_bundle: config.is_present("css_bundle"),
+        css_inline,
+        fingerprint: config.is_present("fingerprint"),
+        image_widths,
+        deny_broken_links: config.is_present("deny_broken_links"),
+        redirects_file: config.is_present("redirects_file"),
+        base_url,
+        author: config.value_of("author").map(|s| s.to_owned()),
+        timezone,
+        git_dates,
+        date_prefix: config.is_present("date_prefix"),
+        words_per_minute,
+        count_code_words: config.is_present("count_code_words"),
+        sitemap: config.is_present("sitemap"),
+        weight_report: config.is_present("weight_report"),
+        weight_budget,
+        fail_over_budget: config.is_present("fail_over_budget"),
     })
 }
@@ -1,215 +1,576 @@
-use crate::config::{FEED_CONTENT_TYPE, FEED_REL, FEED_TYPE};
-use crate::Post;
+use crate::config::{
+    FEED_CONTENT_TYPE, FEED_OPTION_PREFIX, FEED_REL, FEED_TYPE, JSON_FEED_OPTIONS_KEY,
+    JSON_FEED_VERSION, META_TAG_SEPARATOR,
+};
+use crate::{utils, Post};
 
 use atom_syndication as atom;
+use chrono::{Local, SubsecRound};
 use pulldown_cmark as md;
 use quick_xml::events::Event;
 use quick_xml::Reader;
+use std::error::Error;
+use std::fs;
+use std::io::BufReader;
 use std::path::{Path, PathBuf};
 
-enum State {
-    Feed,
-    Info,
-    Title,
-    Generator,
+/// Format of a feed skeleton file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Atom,
+    Rss,
+    Json,
 }
 
-pub struct Meta {
-    pub path: PathBuf,
-    title: String,
-    link: String,
-    lang: Option<String>,
-    generator: Option<String>,
-    generator_uri: Option<String>,
+/// Parts of the skeleton which are kept as-is when filling the feed.
+enum Skeleton {
+    /// The feed along with the attributes of its root element which it would otherwise lose.
+    Atom(Box<atom::Feed>, Vec<(String, String)>),
+    Rss(Box<rss::Channel>),
+    Json(json::JsonValue),
 }
 
-macro_rules! match_or_continue {
-    ( $event_ty:ident ( $event:ident ) ) => {
-        match $event {
-            Event::$event_ty(e) => e,
-            Event::Eof => break,
-            _ => continue,
-        }
-    };
-    ( $event_ty:ident ( $event:ident ) if $guard:expr ) => {
-        match $event {
-            Event::$event_ty($event) if $guard => $event,
-            Event::Eof => break,
-            _ => continue,
-        }
-    };
+/// Which posts make it into the feed and how, configured in the skeleton.
+#[derive(Debug, Default)]
+struct Options {
+    /// Maximum amount of entries, keeping the most recently updated.
+    limit: Option<usize>,
+    /// Only include posts with any of these tags.
+    tags: Vec<String>,
+    /// Only include posts in any of these categories.
+    categories: Vec<String>,
+    /// Include only the summary of the posts instead of their full content.
+    summary_only: bool,
 }
 
-pub fn load_atom_feed(path: &Path) -> quick_xml::Result<Meta> {
-    let mut reader = Reader::from_file(path)?;
-    let mut buffer = Vec::new();
-    let mut state = State::Feed;
-
-    let mut title = None;
-    let mut link = None;
-    let mut lang = None;
-    let mut generator = None;
-    let mut generator_uri = None;
+impl Options {
+    fn set(&mut self, key: &str, value: &str, path: &Path) {
+        let list = || {
+            value
+                .split(META_TAG_SEPARATOR)
+                .map(|s| s.trim().to_owned())
+                .filter(|s| !s.is_empty())
+                .collect()
+        };
+        match key {
+            "limit" => match value.trim().parse() {
+                Ok(limit) => self.limit = Some(limit),
+                Err(_) => eprintln!(
+                    "note: feed limit {:?} is not a number and will be ignored: {:?}",
+                    value, path
+                ),
+            },
+            "tag" => self.tags = list(),
+            "category" => self.categories = list(),
+            "summary-only" => self.summary_only = value.trim() == "true",
+            _ => eprintln!(
+                "note: unknown feed option {:?} will be ignored: {:?}",
+                key, path
+            ),
+        }
+    }
 
-    loop {
-        buffer.clear();
-        let event = reader.read_event(&mut buffer)?;
-        state = match state {
-            State::Feed => {
-                let e = match_or_continue!(Start(event) if event.name() == b"feed");
-                for attr in e.attributes() {
-                    let attr = attr?;
-                    if attr.key == b"xml:lang" {
-                        lang = Some(attr.unescape_and_decode_value(&reader)?);
-                    }
+    /// Read the options from the `pagong:`-prefixed attributes of the root element of a XML skeleton.
+    fn from_xml(attributes: &[(String, String)], path: &Path) -> Self {
+        let mut options = Self::default();
+        for (key, value) in attributes {
+            if let Some(key) = key.strip_prefix(FEED_OPTION_PREFIX) {
+                match quick_xml::escape::unescape(value.as_bytes()) {
+                    Ok(value) => options.set(key, &String::from_utf8_lossy(&value), path),
+                    Err(_) => options.set(key, value, path),
                 }
-                State::Info
             }
-            State::Info => match event {
-                Event::Start(e) if e.name() == b"title" => State::Title,
-                Event::Start(e) if e.name() == b"generator" => {
-                    for attr in e.attributes() {
-                        let attr = attr?;
-                        if attr.key == b"uri" {
-                            generator_uri = Some(attr.unescape_and_decode_value(&reader)?);
-                        }
+        }
+        options
+    }
+
+    /// Read the options from the `_pagong` object of a JSON skeleton.
+    fn from_json(object: &json::JsonValue, path: &Path) -> Self {
+        let mut options = Self::default();
+        for (key, value) in object.entries() {
+            // Options can be written as text like in XML, but real numbers, booleans and lists are nicer.
+            match (key, value) {
+                (_, json::JsonValue::Array(items)) if key == "tag" || key == "category" => {
+                    let list = items
+                        .iter()
+                        .map(|item| {
+                            item.as_str()
+                                .map(str::to_owned)
+                                .unwrap_or_else(|| item.dump())
+                        })
+                        .map(|item| item.trim().to_owned())
+                        .filter(|item| !item.is_empty())
+                        .collect();
+                    if key == "tag" {
+                        options.tags = list;
+                    } else {
+                        options.categories = list;
                     }
-                    State::Generator
                 }
-                Event::Start(e) | Event::Empty(e) if e.name() == b"link" => {
-                    for attr in e.attributes() {
-                        let attr = attr?;
-                        if attr.key == b"href" {
-                            link = Some(attr.unescape_and_decode_value(&reader)?);
-                        }
-                    }
-                    continue;
+                ("limit", json::JsonValue::Number(_)) if value.as_usize().is_some() => {
+                    options.limit = value.as_usize();
                 }
-                Event::Eof => break,
-                _ => continue,
-            },
-            State::Title => {
-                title = Some(match_or_continue!(Text(event)).unescape_and_decode(&reader)?);
-                State::Info
+                ("summary-only", json::JsonValue::Boolean(summary_only)) => {
+                    options.summary_only = *summary_only;
+                }
+                _ => match value.as_str() {
+                    Some(text) => options.set(key, text, path),
+                    None => options.set(key, &value.dump(), path),
+                },
             }
-            State::Generator => {
-                generator = Some(match_or_continue!(Text(event)).unescape_and_decode(&reader)?);
-                State::Info
+        }
+        options
+    }
+}
+
+pub struct Meta {
+    pub path: PathBuf,
+    skeleton: Skeleton,
+    options: Options,
+    link: String,
+}
+
+/// Attributes of the root element of a XML skeleton, with their values still escaped.
+fn root_attributes(path: &Path) -> quick_xml::Result<Vec<(String, String)>> {
+    let mut reader = Reader::from_file(path)?;
+    let mut buffer = Vec::new();
+    loop {
+        match reader.read_event(&mut buffer)? {
+            Event::Start(e) | Event::Empty(e) => {
+                return e
+                    .attributes()
+                    .map(|attr| {
+                        let attr = attr?;
+                        Ok((
+                            String::from_utf8_lossy(attr.key).into_owned(),
+                            String::from_utf8_lossy(&attr.value).into_owned(),
+                        ))
+                    })
+                    .collect();
             }
-        };
+            Event::Eof => return Ok(Vec::new()),
+            _ => {}
+        }
+        buffer.clear();
+    }
+}
+
+/// Load a feed skeleton file of the given format.
+///
+/// JSON files are common enough that those which don't look like a JSON Feed are not considered an error,
+/// and `None` is returned instead.
+pub fn load_feed(path: &Path, format: Format) -> Result<Option<Meta>, Box<dyn Error>> {
+    match format {
+        Format::Atom => load_atom_feed(path).map(Some),
+        Format::Rss => load_rss_feed(path).map(Some),
+        Format::Json => load_json_feed(path),
+    }
+}
+
+fn load_atom_feed(path: &Path) -> Result<Meta, Box<dyn Error>> {
+    let mut feed = atom::Feed::read_from(BufReader::new(fs::File::open(path)?))?;
+    if feed.title.value.is_empty() {
+        eprintln!(
+            "note: atom feed lacks title tag, treating as invalid: {:?}",
+            path
+        );
+        return Err("missing title".into());
     }
 
-    let title = match title {
-        Some(t) => t,
+    let link = match feed
+        .links
+        .iter()
+        .find(|link| link.rel == "alternate")
+        .or_else(|| feed.links.first())
+    {
+        Some(link) => link.href.clone(),
         None => {
             eprintln!(
-                "note: atom feed lacks title tag, treating as invalid: {:?}",
+                "note: atom feed lacks link tag, treating as invalid: {:?}",
                 path
             );
-            return Err(quick_xml::Error::TextNotFound);
+            return Err("missing link".into());
         }
     };
 
-    let link = match link {
-        Some(t) => t,
+    if feed.id.is_empty() {
+        feed.id = link.clone();
+    }
+
+    // Only the attributes which the feed doesn't know about need to be restored later.
+    let attributes = root_attributes(path)?;
+    let options = Options::from_xml(&attributes, path);
+    let attributes = attributes
+        .into_iter()
+        .filter(|(key, _)| {
+            key != "xmlns" && !key.starts_with("xmlns:") && !key.starts_with(FEED_OPTION_PREFIX)
+        })
+        .collect();
+
+    Ok(Meta {
+        path: path.to_path_buf(),
+        skeleton: Skeleton::Atom(Box::new(feed), attributes),
+        options,
+        link,
+    })
+}
+
+fn load_rss_feed(path: &Path) -> Result<Meta, Box<dyn Error>> {
+    let channel = rss::Channel::read_from(BufReader::new(fs::File::open(path)?))?;
+    if channel.link.is_empty() {
+        eprintln!(
+            "note: rss feed lacks link tag, treating as invalid: {:?}",
+            path
+        );
+        return Err("missing link".into());
+    }
+
+    Ok(Meta {
+        path: path.to_path_buf(),
+        options: Options::from_xml(&root_attributes(path)?, path),
+        link: channel.link.clone(),
+        skeleton: Skeleton::Rss(Box::new(channel)),
+    })
+}
+
+fn load_json_feed(path: &Path) -> Result<Option<Meta>, Box<dyn Error>> {
+    let mut object = match json::parse(&fs::read_to_string(path)?) {
+        Ok(object) => object,
+        Err(_) => return Ok(None),
+    };
+    if !object["version"]
+        .as_str()
+        .map(|v| v.starts_with("https://jsonfeed.org/version/"))
+        .unwrap_or(false)
+    {
+        return Ok(None);
+    }
+
+    if object["title"].as_str().is_none() {
+        eprintln!(
+            "note: json feed lacks title, treating as invalid: {:?}",
+            path
+        );
+        return Err("missing title".into());
+    }
+
+    let link = match object["home_page_url"].as_str() {
+        Some(t) => t.to_owned(),
         None => {
             eprintln!(
-                "note: atom feed lacks link tag, treating as invalid: {:?}",
+                "note: json feed lacks home_page_url, treating as invalid: {:?}",
                 path
             );
-            return Err(quick_xml::Error::TextNotFound);
+            return Err("missing home_page_url".into());
         }
     };
 
-    Ok(Meta {
+    let options = Options::from_json(&object.remove(JSON_FEED_OPTIONS_KEY), path);
+
+    Ok(Some(Meta {
         path: path.to_path_buf(),
-        title,
+        options,
         link,
-        lang,
-        generator,
-        generator_uri,
-    })
+        skeleton: Skeleton::Json(object),
+    }))
 }
 
-pub fn fill_atom_feed(feed: &Meta, md_files: &[Post]) -> String {
+/// The posts which belong to the feed (those next to it or in any directory below it, and matching its filters),
+/// most recently updated first.
+fn feed_entries<'a>(feed: &Meta, md_files: &'a [Post]) -> Vec<&'a Post> {
     let parent = feed.path.parent().unwrap();
+    let options = &feed.options;
+    let mut entries = md_files
+        .iter()
+        .filter(|md| md.path.starts_with(parent))
+        .filter(|md| options.tags.is_empty() || md.tags.iter().any(|t| options.tags.contains(t)))
+        .filter(|md| options.categories.is_empty() || options.categories.contains(&md.category))
+        .collect::<Vec<_>>();
 
-    let mut entries = Vec::new();
-    let mut last_updated = None;
+    entries.sort_by_key(|md| std::cmp::Reverse((md.updated, md.date)));
+    if let Some(limit) = options.limit {
+        entries.truncate(limit);
+    }
+    entries
+}
 
-    for md in md_files {
-        if md.path.starts_with(parent) {
-            if let Some(updated) = last_updated {
-                last_updated = Some(md.updated.max(updated));
-            } else {
-                last_updated = Some(md.updated);
+fn entry_id(feed: &Meta, md: &Post) -> String {
+    let mut s = feed.link.clone();
+    s.push_str(&md.uri);
+    s
+}
+
+/// Absolute URL to the post's page.
+fn entry_url(feed: &Meta, md: &Post) -> String {
+    utils::absolute_url(&feed.link, &md.uri, &md.uri)
+}
+
+/// The post's contents as rendered in its page, with every URI made absolute so that it works in feed readers.
+///
+/// If the feed only wants summaries, the summary in the metadata is used, or the first paragraph otherwise.
+fn entry_html<F: Fn(&Post) -> String>(feed: &Meta, md: &Post, render: &F) -> String {
+    let html = match (feed.options.summary_only, md.summary.as_ref()) {
+        (true, Some(summary)) => {
+            let mut html = String::from("<p>");
+            md::escape::escape_html(&mut html, summary).unwrap();
+            html.push_str("</p>");
+            return html;
+        }
+        (true, None) => {
+            let html = render(md);
+            match html.find("</p>") {
+                Some(end) => html[html.find("<p>").unwrap_or(0)..end + "</p>".len()].to_owned(),
+                None => html,
             }
+        }
+        (false, _) => render(md),
+    };
 
-            entries.push(atom::Entry {
-                title: md.title.clone().into(),
-                id: {
-                    let mut s = feed.link.clone();
-                    s.push_str(&md.uri);
-                    s
-                },
-                updated: md.updated.and_hms(0, 0, 0).into(),
-                published: Some(md.date.and_hms(0, 0, 0).into()),
-                categories: vec![atom::Category {
-                    term: md.category.clone(),
-                    ..atom::Category::default()
-                }],
-                content: Some(atom::Content {
-                    value: {
-                        let mut html = String::new();
-                        md::html::push_html(&mut html, md::Parser::new(&md.markdown));
-                        let mut escaped = String::new();
-                        md::escape::escape_html(&mut escaped, &html).unwrap();
-                        Some(escaped)
-                    },
-                    content_type: Some(FEED_CONTENT_TYPE.to_string()),
-                    ..atom::Content::default()
-                }),
-                ..atom::Entry::default()
-            });
+    utils::map_html_uris(&html, |uri| {
+        Some(utils::absolute_url(&feed.link, &md.uri, uri)).filter(|url| url != uri)
+    })
+}
+
+/// The category and tags of the post, without duplicates.
+fn entry_categories(md: &Post) -> Vec<String> {
+    let mut categories = vec![md.category.clone()];
+    for tag in md.tags.iter() {
+        if !categories.contains(tag) {
+            categories.push(tag.clone());
         }
     }
+    categories
+}
+
+/// Split an author written as "Name <email>" into its name and email.
+fn split_author(author: &str) -> (&str, Option<&str>) {
+    match (author.find('<'), author.trim_end().strip_suffix('>')) {
+        (Some(start), Some(rest)) => (author[..start].trim(), Some(rest[start + 1..].trim())),
+        _ => (author.trim(), None),
+    }
+}
 
+fn self_link(feed: &Meta) -> String {
     let mut self_link = feed.link.trim_end_matches('/').to_owned();
     self_link.push('/');
-    self_link.push_str(&feed.path.file_name().unwrap().to_str().unwrap());
+    self_link.push_str(feed.path.file_name().unwrap().to_str().unwrap());
+    self_link
+}
 
-    if let Some(lang) = feed.lang.as_ref() {
-        eprintln!(
-            "note: feed lang '{}' is currently ignored: see gh/atom/issues/54",
-            lang
-        );
+/// Fill the feed with an entry for every post that belongs to it, using `render` to produce their HTML.
+pub fn fill_feed<F: Fn(&Post) -> String>(feed: &Meta, md_files: &[Post], render: F) -> String {
+    let entries = feed_entries(feed, md_files);
+    match &feed.skeleton {
+        Skeleton::Atom(skeleton, attributes) => {
+            fill_atom_feed(feed, skeleton, attributes, &entries, &render)
+        }
+        Skeleton::Rss(channel) => fill_rss_feed(feed, channel, &entries, &render),
+        Skeleton::Json(object) => fill_json_feed(feed, object, &entries, &render),
     }
+}
 
-    atom::Feed {
-        title: feed.title.clone().into(),
-        id: feed.link.clone(),
-        updated: last_updated
-            .map(|d| d.and_hms(0, 0, 0).into())
-            .unwrap_or_else(|| chrono::offset::Local::now().into()),
-        entries,
-        generator: feed.generator.clone().map(|value| atom::Generator {
-            value,
-            uri: feed.generator_uri.clone(),
-            ..atom::Generator::default()
-        }),
-        links: vec![
-            atom::Link {
-                href: feed.link.clone(),
-                ..atom::Link::default()
-            },
-            atom::Link {
-                href: self_link,
-                rel: FEED_REL.into(),
-                mime_type: Some(FEED_TYPE.to_owned()),
+fn fill_atom_feed<F: Fn(&Post) -> String>(
+    feed: &Meta,
+    skeleton: &atom::Feed,
+    attributes: &[(String, String)],
+    md_files: &[&Post],
+    render: &F,
+) -> String {
+    let mut entries = Vec::new();
+    let mut last_updated = None;
+
+    for md in md_files {
+        if let Some(updated) = last_updated {
+            last_updated = Some(md.updated.max(updated));
+        } else {
+            last_updated = Some(md.updated);
+        }
+
+        entries.push(atom::Entry {
+            title: md.title.clone().into(),
+            id: entry_id(feed, md),
+            updated: md.updated,
+            published: Some(md.date),
+            authors: md
+                .author
+                .iter()
+                .map(|author| {
+                    let (name, email) = split_author(author);
+                    atom::Person {
+                        name: name.to_owned(),
+                        email: email.map(|e| e.to_owned()),
+                        ..atom::Person::default()
+                    }
+                })
+                .collect(),
+            categories: entry_categories(md)
+                .into_iter()
+                .map(|term| atom::Category {
+                    term,
+                    ..atom::Category::default()
+                })
+                .collect(),
+            links: vec![atom::Link {
+                href: entry_url(feed, md),
+                mime_type: Some("text/html".to_owned()),
                 ..atom::Link::default()
+            }],
+            summary: md.summary.clone().map(Into::into),
+            content: Some(atom::Content {
+                value: {
+                    let mut escaped = String::new();
+                    md::escape::escape_html(&mut escaped, &entry_html(feed, md, render)).unwrap();
+                    Some(escaped)
+                },
+                content_type: Some(FEED_CONTENT_TYPE.to_string()),
+                ..atom::Content::default()
+            }),
+            ..atom::Entry::default()
+        });
+    }
+
+    let mut atom = skeleton.clone();
+    atom.entries = entries;
+    if let Some(updated) = last_updated {
+        atom.updated = updated;
+    } else if atom.updated == atom::Feed::default().updated {
+        // The skeleton had no `<updated>` either, and the feed must have one.
+        atom.updated = Local::now().trunc_subsecs(0).into();
+    }
+    if !atom.links.iter().any(|link| link.rel == FEED_REL) {
+        atom.links.push(atom::Link {
+            href: self_link(feed),
+            rel: FEED_REL.into(),
+            mime_type: Some(FEED_TYPE.to_owned()),
+            ..atom::Link::default()
+        });
+    }
+
+    let mut xml = atom.to_string();
+    if let Some(index) = xml.find("<feed") {
+        let attributes = attributes
+            .iter()
+            .map(|(key, value)| format!(" {}=\"{}\"", key, value))
+            .collect::<String>();
+        xml.insert_str(index + "<feed".len(), &attributes);
+    }
+    xml
+}
+
+fn fill_rss_feed<F: Fn(&Post) -> String>(
+    feed: &Meta,
+    channel: &rss::Channel,
+    md_files: &[&Post],
+    render: &F,
+) -> String {
+    let mut channel = channel.clone();
+    channel.items = md_files
+        .iter()
+        .map(|md| {
+            // The summary goes in the `description` when there is one, with the contents next to it.
+            let html = entry_html(feed, md, render);
+            let (description, content) = match md.summary.as_ref() {
+                Some(summary) if !feed.options.summary_only => (summary.clone(), Some(html)),
+                _ => (html, None),
+            };
+            rss::Item {
+                title: Some(md.title.clone()),
+                // RSS wants "email (Name)", but a name alone is better than no author at all.
+                author: md.author.as_ref().map(|author| match split_author(author) {
+                    (name, Some(email)) if !name.is_empty() => format!("{} ({})", email, name),
+                    (_, Some(email)) => email.to_owned(),
+                    (name, None) => name.to_owned(),
+                }),
+                link: Some(entry_url(feed, md)),
+                guid: Some(rss::Guid {
+                    value: entry_id(feed, md),
+                    permalink: false,
+                }),
+                pub_date: Some(md.date.to_rfc2822()),
+                categories: entry_categories(md)
+                    .into_iter()
+                    .map(|name| rss::Category {
+                        name,
+                        ..rss::Category::default()
+                    })
+                    .collect(),
+                description: Some(description),
+                content,
+                ..rss::Item::default()
+            }
+        })
+        .collect();
+    channel.last_build_date = md_files
+        .iter()
+        .map(|md| md.updated)
+        .max()
+        .map(|d| d.to_rfc2822());
+    channel.to_string()
+}
+
+fn fill_json_feed<F: Fn(&Post) -> String>(
+    feed: &Meta,
+    object: &json::JsonValue,
+    md_files: &[&Post],
+    render: &F,
+) -> String {
+    let mut object = object.clone();
+    object["version"] = JSON_FEED_VERSION.into();
+    if object["feed_url"].is_null() {
+        object["feed_url"] = self_link(feed).into();
+    }
+
+    let mut items = json::JsonValue::new_array();
+    for md in md_files {
+        let mut item = json::JsonValue::new_object();
+        item["id"] = entry_id(feed, md).into();
+        item["url"] = entry_url(feed, md).into();
+        item["title"] = md.title.clone().into();
+        item["content_html"] = entry_html(feed, md, render).into();
+        item["date_published"] = md.date.to_rfc3339().into();
+        item["date_modified"] = md.updated.to_rfc3339().into();
+        item["tags"] = entry_categories(md).into();
+        if let Some(summary) = md.summary.as_ref() {
+            item["summary"] = summary.clone().into();
+        }
+        if let Some(author) = md.author.as_ref() {
+            let mut person = json::JsonValue::new_object();
+            person["name"] = split_author(author).0.into();
+            item["authors"] = vec![person].into();
+        }
+        items.push(item).unwrap();
+    }
+    object["items"] = items;
+
+    json::stringify_pretty(object, 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_options() {
+        let path = Path::new("feed.json");
+        let options = Options::from_json(
+            &json::object! {
+                "limit": 20,
+                "tag": ["rust", "go"],
+                "category": "blog, news",
+                "summary-only": true,
             },
-        ],
-        ..atom::Feed::default()
+            path,
+        );
+        assert_eq!(options.limit, Some(20));
+        assert_eq!(options.tags, vec!["rust", "go"]);
+        assert_eq!(options.categories, vec!["blog", "news"]);
+        assert!(options.summary_only);
+
+        let options = Options::from_json(
+            &json::object! { "limit": "5", "summary-only": "true" },
+            path,
+        );
+        assert_eq!(options.limit, Some(5));
+        assert!(options.summary_only);
     }
-    .to_string()
 }
@@ -0,0 +1,182 @@
+use crate::config::{FINGERPRINT_EXTENSIONS, STYLE_FILE_EXT};
+use crate::{style, utils};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Mapping from the original URIs of the fingerprinted files to their new URIs.
+#[derive(Default)]
+pub struct Manifest {
+    uris: BTreeMap<String, String>,
+}
+
+pub fn should_fingerprint(path: &Path) -> bool {
+    FINGERPRINT_EXTENSIONS
+        .iter()
+        .any(|ext| utils::has_extension(path, ext))
+}
+
+/// Sort the CSS files so that those imported by another one come before it, and are fingerprinted first.
+pub fn import_order<'a>(root: &Path, files: Vec<&'a PathBuf>) -> Vec<&'a PathBuf> {
+    let uris = files
+        .iter()
+        .map(|file| utils::path_to_uri(root, file))
+        .collect::<Vec<_>>();
+    let imports = files
+        .iter()
+        .zip(uris.iter())
+        .map(|(file, uri)| {
+            let mut imported = Vec::new();
+            let css = fs::read_to_string(file).unwrap_or_default();
+            style::map_urls(&css, |url| {
+                let (path, _) = utils::split_uri_suffix(url);
+                if !path.is_empty() && !utils::is_external_uri(url) {
+                    let target = utils::join_uri(uri, path);
+                    imported.extend(uris.iter().position(|uri| *uri == target));
+                }
+                None
+            });
+            imported
+        })
+        .collect::<Vec<_>>();
+
+    fn visit(index: usize, imports: &[Vec<usize>], visited: &mut [bool], order: &mut Vec<usize>) {
+        if !visited[index] {
+            visited[index] = true;
+            for &imported in imports[index].iter() {
+                visit(imported, imports, visited, order);
+            }
+            order.push(index);
+        }
+    }
+
+    let mut visited = vec![false; files.len()];
+    let mut order = Vec::with_capacity(files.len());
+    for index in 0..files.len() {
+        visit(index, &imports, &mut visited, &mut order);
+    }
+    order.into_iter().map(|index| files[index]).collect()
+}
+
+impl Manifest {
+    pub fn insert(&mut self, original: String, fingerprinted: String) {
+        self.uris.insert(original, fingerprinted);
+    }
+
+    /// The fingerprinted URI of the file, or the same URI if it was not fingerprinted.
+    pub fn get<'a>(&'a self, uri: &'a str) -> &'a str {
+        self.uris.get(uri).map(|s| s.as_str()).unwrap_or(uri)
+    }
+
+    /// Rewrite a URI found in the file at `base` if it references a fingerprinted file.
+    ///
+    /// Relative URIs remain relative, and the query and fragment are preserved.
+    pub fn rewrite_uri(&self, base: &str, uri: &str) -> Option<String> {
+        let (path, suffix) = utils::split_uri_suffix(uri);
+        if path.is_empty() || utils::is_external_uri(uri) {
+            return None;
+        }
+
+        let fingerprinted = self.uris.get(&utils::join_uri(base, path))?;
+        let mut result = if path.starts_with('/') {
+            fingerprinted.clone()
+        } else {
+            utils::get_relative_uri(base, fingerprinted)
+        };
+        result.push_str(suffix);
+        Some(result)
+    }
+
+    /// Rewrite the `url()`s and `@import`s in the CSS found at `uri` which reference fingerprinted files.
+    pub fn rewrite_css(&self, uri: &str, css: &str) -> String {
+        style::map_urls(css, |url| self.rewrite_uri(uri, url))
+    }
+
+    /// Rewrite the attributes in the HTML found at `uri` which reference fingerprinted files.
+    pub fn rewrite_html(&self, uri: &str, html: &str) -> String {
+        if self.uris.is_empty() {
+            return html.to_owned();
+        }
+
+        utils::map_html_uris(html, |value| self.rewrite_uri(uri, value))
+    }
+
+    /// Rewrite the references to fingerprinted files in a CSS or HTML file copied as-is from `uri`.
+    pub fn rewrite_file(&self, uri: &str, content: &str) -> String {
+        if utils::has_extension(Path::new(uri), STYLE_FILE_EXT) {
+            self.rewrite_css(uri, content)
+        } else {
+            self.rewrite_html(uri, content)
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut object = json::JsonValue::new_object();
+        for (original, fingerprinted) in self.uris.iter() {
+            object[original.as_str()] = fingerprinted.as_str().into();
+        }
+        json::stringify_pretty(object, 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> Manifest {
+        let mut manifest = Manifest::default();
+        manifest.insert("/img/a.png".to_owned(), "/img/a.123.png".to_owned());
+        manifest.insert(
+            "/img/a-480w.png".to_owned(),
+            "/img/a.123-480w.png".to_owned(),
+        );
+        manifest
+    }
+
+    #[test]
+    fn uri() {
+        let manifest = manifest();
+        assert_eq!(
+            manifest.rewrite_uri("/blog/post.html", "../img/a.png#x"),
+            Some("../img/a.123.png#x".to_owned())
+        );
+        assert_eq!(
+            manifest.rewrite_uri("/blog/post.html", "/img/a.png"),
+            Some("/img/a.123.png".to_owned())
+        );
+        assert_eq!(manifest.rewrite_uri("/blog/post.html", "b.png"), None);
+    }
+
+    #[test]
+    fn html() {
+        let manifest = manifest();
+        assert_eq!(
+            manifest.rewrite_html(
+                "/index.html",
+                "<img src=img/a.png srcset=\"img/a-480w.png 480w, img/a.png 960w\"><a href=\"b.html\">"
+            ),
+            "<img src=img/a.123.png srcset=\"img/a.123-480w.png 480w, img/a.123.png 960w\"><a href=\"b.html\">"
+        );
+    }
+
+    #[test]
+    fn copied_files() {
+        let mut manifest = manifest();
+        manifest.insert("/css/b.css".to_owned(), "/css/b.456.css".to_owned());
+        assert_eq!(
+            manifest.rewrite_file(
+                "/css/a.css",
+                "@import \"b.css\"; @import url('b.css'); @import 'c.css'; a { background: url(../img/a.png) }"
+            ),
+            "@import \"b.456.css\"; @import url('b.456.css'); @import 'c.css'; a { background: url(../img/a.123.png) }"
+        );
+        assert_eq!(
+            manifest.rewrite_file(
+                "/about.html",
+                "<link rel=stylesheet href=\"css/b.css\"><img src='/img/a.png'>"
+            ),
+            "<link rel=stylesheet href=\"css/b.456.css\"><img src='/img/a.123.png'>"
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use crate::config::{TOML_FRONT_MATTER_FENCE, YAML_FRONT_MATTER_FENCE};
+use crate::meta::MetaValue;
+
+use std::collections::HashMap;
+use std::io;
+use std::ops::Range;
+use yaml_rust::{Yaml, YamlLoader};
+
+/// Kind of front matter found at the start of a file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Yaml,
+    Toml,
+}
+
+/// Find the front matter at the start of the markdown, along with the range it spans (fences included)
+/// and the range of its contents.
+pub fn find(markdown: &str) -> Option<(Format, Range<usize>, Range<usize>)> {
+    let (format, fence) = if markdown.starts_with(YAML_FRONT_MATTER_FENCE) {
+        (Format::Yaml, YAML_FRONT_MATTER_FENCE)
+    } else if markdown.starts_with(TOML_FRONT_MATTER_FENCE) {
+        (Format::Toml, TOML_FRONT_MATTER_FENCE)
+    } else {
+        return None;
+    };
+
+    // The opening fence must be on a line of its own.
+    let start = markdown.find('\n')? + 1;
+    if markdown[..start].trim_end() != fence {
+        return None;
+    }
+
+    let mut offset = start;
+    for line in markdown[start..].split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == fence || (format == Format::Yaml && trimmed == "...") {
+            return Some((format, 0..offset + line.len(), start..offset));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Parse the contents of the front matter into typed metadata.
+pub fn parse(format: Format, source: &str) -> io::Result<HashMap<String, MetaValue>> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+
+    match format {
+        Format::Yaml => {
+            let docs = YamlLoader::load_from_str(source).map_err(|e| invalid(e.to_string()))?;
+            match docs.into_iter().next() {
+                Some(Yaml::Hash(hash)) => Ok(hash
+                    .iter()
+                    .filter_map(|(k, v)| Some((yaml_key(k)?, yaml_value(v)?)))
+                    .collect()),
+                Some(Yaml::Null) | None => Ok(HashMap::new()),
+                Some(_) => Err(invalid("front matter is not a mapping".to_owned())),
+            }
+        }
+        Format::Toml => {
+            let table =
+                toml::from_str::<toml::value::Table>(source).map_err(|e| invalid(e.to_string()))?;
+            Ok(table
+                .iter()
+                .map(|(k, v)| (k.clone(), toml_value(v)))
+                .collect())
+        }
+    }
+}
+
+fn yaml_key(key: &Yaml) -> Option<String> {
+    match yaml_value(key)? {
+        MetaValue::List(_) | MetaValue::Table(_) => None,
+        key => Some(key.to_string()),
+    }
+}
+
+fn yaml_value(value: &Yaml) -> Option<MetaValue> {
+    Some(match value {
+        // YAML has no date type of its own, so they're found among the strings.
+        Yaml::String(s) => MetaValue::from_text(s.clone()),
+        Yaml::Real(s) => s
+            .parse()
+            .map(MetaValue::Float)
+            .unwrap_or_else(|_| MetaValue::String(s.clone())),
+        Yaml::Integer(i) => MetaValue::Integer(*i),
+        Yaml::Boolean(b) => MetaValue::Bool(*b),
+        Yaml::Array(items) => MetaValue::List(items.iter().filter_map(yaml_value).collect()),
+        Yaml::Hash(hash) => MetaValue::Table(
+            hash.iter()
+                .filter_map(|(k, v)| Some((yaml_key(k)?, yaml_value(v)?)))
+                .collect(),
+        ),
+        _ => return None,
+    })
+}
+
+fn toml_value(value: &toml::Value) -> MetaValue {
+    match value {
+        toml::Value::String(s) => MetaValue::String(s.clone()),
+        toml::Value::Integer(i) => MetaValue::Integer(*i),
+        toml::Value::Float(f) => MetaValue::Float(*f),
+        toml::Value::Boolean(b) => MetaValue::Bool(*b),
+        toml::Value::Datetime(d) => MetaValue::from_text(d.to_string()),
+        toml::Value::Array(items) => MetaValue::List(items.iter().map(toml_value).collect()),
+        toml::Value::Table(table) => MetaValue::Table(
+            table
+                .iter()
+                .map(|(k, v)| (k.clone(), toml_value(v)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yaml() {
+        let md = "---\ntitle: Hello\ntags: [a, b]\ndraft: true\nauthor:\n  name: Me\n---\n# Hi\n";
+        let (format, range, contents) = find(md).unwrap();
+        assert_eq!(format, Format::Yaml);
+        assert_eq!(&md[range.end..], "# Hi\n");
+
+        let meta = parse(format, &md[contents]).unwrap();
+        assert_eq!(meta["title"].to_string(), "Hello");
+        assert_eq!(meta["tags"].as_list(), ["a", "b"]);
+        assert_eq!(meta["draft"], MetaValue::Bool(true));
+        assert_eq!(
+            meta["author"].get_path("name"),
+            Some(&MetaValue::String("Me".to_owned()))
+        );
+    }
+
+    #[test]
+    fn toml() {
+        let md = "+++\ntitle = \"Hello\"\ndate = 2020-02-20\n[extra]\nlang = \"en\"\n+++\nText";
+        let (format, range, contents) = find(md).unwrap();
+        assert_eq!(format, Format::Toml);
+        assert_eq!(&md[range.end..], "Text");
+
+        let meta = parse(format, &md[contents]).unwrap();
+        assert_eq!(meta["title"].to_string(), "Hello");
+        assert!(matches!(meta["date"], MetaValue::Date(_)));
+        assert_eq!(meta["extra"].get_path("lang").unwrap().to_string(), "en");
+    }
+
+    #[test]
+    fn unclosed() {
+        assert!(find("---\ntitle: Hello\n").is_none());
+        assert!(find("----\n").is_none());
+    }
+}
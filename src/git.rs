@@ -0,0 +1,80 @@
+use chrono::{DateTime, FixedOffset};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const COMMIT_MARKER: char = '\u{1}';
+
+/// Dates of the first and last commits touching every file in a git repository.
+pub struct GitDates {
+    dates: HashMap<PathBuf, (DateTime<FixedOffset>, DateTime<FixedOffset>)>,
+}
+
+/// Run `git` inside the directory and return its output.
+fn git(dir: &Path, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+impl GitDates {
+    /// Read the history of the repository containing the directory, once, with a single `git log`.
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        let toplevel = fs::canonicalize(git(dir, &["rev-parse", "--show-toplevel"])?.trim())?;
+
+        // The oldest commit of a shallow clone lists every file, so they would all get its date.
+        if git(dir, &["rev-parse", "--is-shallow-repository"])?.trim() == "true" {
+            return Err(io::Error::other(
+                "cannot take post dates from a shallow clone, fetch its full history first \
+                 (for example, with `git fetch --unshallow`)",
+            ));
+        }
+
+        // Every commit starts with a byte that can't be in a path, followed by its date. With `-z`,
+        // file names are separated by NUL and never quoted.
+        let log = git(
+            dir,
+            &[
+                "log",
+                "-z",
+                &format!("--format={}%aI", COMMIT_MARKER),
+                "--name-only",
+                "--no-renames",
+            ],
+        )?;
+
+        // Commits are listed newest first.
+        let mut dates = HashMap::new();
+        let mut date = None;
+        for field in log.split('\0') {
+            if let Some(header) = field.strip_prefix(COMMIT_MARKER) {
+                date = DateTime::parse_from_rfc3339(header.trim()).ok();
+                continue;
+            }
+            // The first file of a commit follows the newline that ends its header.
+            let file = field.strip_prefix('\n').unwrap_or(field);
+            if let (Some(date), false) = (date, file.is_empty()) {
+                dates
+                    .entry(toplevel.join(file))
+                    .and_modify(|(created, _)| *created = date)
+                    .or_insert((date, date));
+            }
+        }
+
+        Ok(Self { dates })
+    }
+
+    /// Dates of the first and last commits of the file, if it has been committed.
+    pub fn get(&self, path: &Path) -> Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+        self.dates.get(&fs::canonicalize(path).ok()?).copied()
+    }
+}
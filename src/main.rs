@@ -1,11 +1,22 @@
 mod adaptor;
 mod blog;
+mod check;
 mod config;
 mod feed;
+mod fingerprint;
+mod front_matter;
+mod git;
+mod media;
+mod meta;
 mod post;
 mod processor;
+mod redirect;
+mod series;
+mod sitemap;
+mod style;
 mod template;
 mod utils;
+mod weight;
 
 use adaptor::AdaptorExt;
 use post::Post;
@@ -29,6 +40,7 @@ fn main() -> io::Result<()> {
     dist.push(config::TARGET_PATH);
 
     let scan = blog::scan_dir(&config, content)?;
+    check::check_links(&config, &scan)?;
     blog::generate_from_scan(&config, scan, dist)?;
 
     Ok(())
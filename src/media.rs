@@ -0,0 +1,106 @@
+use crate::config::RASTER_IMAGE_EXTENSIONS;
+use crate::{utils, Post};
+
+use image::imageops::FilterType;
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Intrinsic size of a raster image referenced by some post.
+#[derive(Debug, Clone, Copy)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Image {
+    /// Widths out of `widths` which are smaller than the image itself, in ascending order.
+    pub fn variant_widths(&self, widths: &[u32]) -> Vec<u32> {
+        let mut result = widths
+            .iter()
+            .copied()
+            .filter(|w| *w < self.width)
+            .collect::<Vec<_>>();
+        result.sort_unstable();
+        result.dedup();
+        result
+    }
+}
+
+pub fn is_raster_image(path: &Path) -> bool {
+    RASTER_IMAGE_EXTENSIONS
+        .iter()
+        .any(|ext| utils::has_extension(path, ext))
+}
+
+/// Find the local raster images referenced by the posts, by URI, along with their size.
+pub fn scan_images(root: &Path, posts: &[Post]) -> HashMap<String, Image> {
+    let mut images = HashMap::new();
+    for post in posts {
+        for event in Parser::new_ext(&post.markdown, Options::all()) {
+            let path = match event {
+                Event::Start(Tag::Image(_, dest, _)) => {
+                    match utils::resolve_uri(root, &post.path, &dest) {
+                        Some(path) => path,
+                        None => continue,
+                    }
+                }
+                _ => continue,
+            };
+
+            let uri = utils::path_to_uri(root, &path);
+            if images.contains_key(&uri) || !is_raster_image(&path) {
+                continue;
+            }
+
+            match image::image_dimensions(&path) {
+                Ok((width, height)) => {
+                    images.insert(uri, Image { width, height });
+                }
+                Err(e) => eprintln!("note: failed to read image size: {}: {:?}", e, path),
+            }
+        }
+    }
+    images
+}
+
+/// Generate the downscaled variants of the image at `src` next to `dst`.
+///
+/// Variants newer than the source image are assumed to be up-to-date and are not generated again.
+pub fn generate_variants(src: &Path, dst: &Path, image: &Image, widths: &[u32]) -> io::Result<()> {
+    let src_modified = fs::metadata(src)?.modified()?;
+    let dst = dst.to_str().expect("bad image path");
+
+    let mut loaded = None;
+    for width in image.variant_widths(widths) {
+        let variant = PathBuf::from(utils::variant_name(dst, width));
+        if let Ok(modified) = fs::metadata(&variant).and_then(|m| m.modified()) {
+            if modified >= src_modified {
+                continue;
+            }
+        }
+
+        if loaded.is_none() {
+            loaded = match image::open(src) {
+                Ok(img) => Some(img),
+                Err(e) => {
+                    eprintln!("note: failed to load image: {}: {:?}", e, src);
+                    return Ok(());
+                }
+            };
+        }
+
+        let height = (image.height as u64 * width as u64 / image.width as u64).max(1) as u32;
+        let resized = loaded
+            .as_ref()
+            .unwrap()
+            .resize_exact(width, height, FilterType::Lanczos3);
+        if let Err(e) = resized.save(&variant) {
+            eprintln!("note: failed to save image variant: {}: {:?}", e, variant);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,296 @@
+use crate::config::{DATE_FMT, META_TAG_SEPARATOR};
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Value of a metadata key, which may be more than just text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetaValue {
+    String(String),
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    Date(NaiveDate),
+    DateTime(DateTime<FixedOffset>),
+    List(Vec<MetaValue>),
+    Table(BTreeMap<String, MetaValue>),
+    /// Text whose inferred type would not be written the same way (as in `007` or `[RFC] Foo`),
+    /// which is kept as-is and only interpreted when comparing or converting it.
+    Raw(String),
+}
+
+impl MetaValue {
+    /// Infer the type of a value written as plain text (as is the case in the meta block).
+    ///
+    /// Lists must be written between brackets (as in `[a, b]`), since text may contain commas.
+    /// Values which would not be written back the same way once typed are kept as `Raw` text.
+    pub fn infer(value: &str) -> Self {
+        let value = value.trim();
+        Self::keep_text(value, Self::typed(value))
+    }
+
+    /// Text which may be a date (or an RFC 3339 timestamp), but is otherwise kept as text.
+    pub fn from_text(value: String) -> Self {
+        let typed = Self::typed_text(value.clone());
+        Self::keep_text(&value, typed)
+    }
+
+    fn keep_text(text: &str, typed: Self) -> Self {
+        match typed {
+            Self::String(_) => typed,
+            typed if typed.to_string() == text => typed,
+            _ => Self::Raw(text.to_owned()),
+        }
+    }
+
+    fn typed(value: &str) -> Self {
+        let items = value.strip_prefix('[').and_then(|v| v.strip_suffix(']'));
+        // Text such as `[RFC] Foo [v2]` only looks like a list from the outside.
+        if let Some(items) = items.filter(|items| !items.contains(['[', ']'])) {
+            return Self::List(
+                items
+                    .split(META_TAG_SEPARATOR)
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(Self::infer)
+                    .collect(),
+            );
+        }
+
+        match value {
+            "true" => return Self::Bool(true),
+            "false" => return Self::Bool(false),
+            _ => {}
+        }
+        if let Ok(i) = value.parse() {
+            return Self::Integer(i);
+        }
+        // Don't let `f64` parse things like "inf" or "NaN".
+        if value.chars().any(|c| c.is_ascii_digit())
+            && value
+                .chars()
+                .all(|c| c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E'))
+        {
+            if let Ok(f) = value.parse() {
+                return Self::Float(f);
+            }
+        }
+        Self::typed_text(value.to_owned())
+    }
+
+    fn typed_text(value: String) -> Self {
+        if let Ok(date) = NaiveDate::parse_from_str(&value, DATE_FMT) {
+            Self::Date(date)
+        } else if let Ok(datetime) = DateTime::parse_from_rfc3339(&value) {
+            Self::DateTime(datetime)
+        } else {
+            Self::String(value)
+        }
+    }
+
+    /// Find the value under a dot-separated path of keys inside tables (as in `author.name`).
+    pub fn get_path<'a>(&'a self, path: &str) -> Option<&'a MetaValue> {
+        path.split('.').try_fold(self, |value, key| match value {
+            Self::Table(table) => table.get(key),
+            _ => None,
+        })
+    }
+
+    /// The typed value of `Raw` text, or the value itself otherwise.
+    fn resolved(&self) -> Cow<'_, Self> {
+        match self {
+            Self::Raw(text) => Cow::Owned(Self::typed(text)),
+            value => Cow::Borrowed(value),
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Raw(_) => self.resolved().as_bool(),
+            Self::Bool(b) => Some(*b),
+            Self::String(s) => match s.to_ascii_lowercase().as_str() {
+                "true" | "yes" => Some(true),
+                "false" | "no" => Some(false),
+                _ => None,
+            },
+            Self::Integer(i) => Some(*i != 0),
+            _ => None,
+        }
+    }
+
+    /// The value as a list of text, splitting plain text on commas (as is done with tags).
+    pub fn as_list(&self) -> Vec<String> {
+        match self {
+            Self::List(items) => items.iter().map(|v| v.to_string()).collect(),
+            Self::Raw(s) if s.starts_with('[') => match self.resolved().as_ref() {
+                list @ Self::List(_) => list.as_list(),
+                _ => Self::String(s.clone()).as_list(),
+            },
+            Self::String(s) | Self::Raw(s) => s
+                .split(META_TAG_SEPARATOR)
+                .map(|s| s.trim().to_owned())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            value => vec![value.to_string()],
+        }
+    }
+
+    /// Whether the value is (or contains, if it's a list) the text.
+    pub fn matches(&self, text: &str) -> bool {
+        match self {
+            Self::Raw(_) => self.resolved().matches(text),
+            Self::List(items) => items.iter().any(|v| v.matches(text)),
+            value => match (value, Self::infer(text)) {
+                // A plain date matches any time of that day.
+                (Self::DateTime(datetime), Self::Date(date)) => datetime.date_naive() == date,
+                (value, pattern) => value.compare(&pattern) == Ordering::Equal,
+            },
+        }
+    }
+
+    /// Compare two values, numerically if both are numbers, and as text if they're of different types.
+    pub fn compare(&self, other: &Self) -> Ordering {
+        if matches!(self, Self::Raw(_)) || matches!(other, Self::Raw(_)) {
+            return self.resolved().compare(&other.resolved());
+        }
+        match (self, other) {
+            (Self::String(a), Self::String(b)) => a.cmp(b),
+            (Self::Bool(a), Self::Bool(b)) => a.cmp(b),
+            (Self::Integer(a), Self::Integer(b)) => a.cmp(b),
+            (Self::Integer(a), Self::Float(b)) => (*a as f64).total_cmp(b),
+            (Self::Float(a), Self::Integer(b)) => a.total_cmp(&(*b as f64)),
+            (Self::Float(a), Self::Float(b)) => a.total_cmp(b),
+            (Self::Date(a), Self::Date(b)) => a.cmp(b),
+            (Self::DateTime(a), Self::DateTime(b)) => a.cmp(b),
+            // A plain date is considered to be at the start of that day.
+            (Self::Date(a), Self::DateTime(b)) => a.and_time(NaiveTime::MIN).cmp(&b.naive_local()),
+            (Self::DateTime(a), Self::Date(b)) => a.naive_local().cmp(&b.and_time(NaiveTime::MIN)),
+            (Self::List(a), Self::List(b)) => a
+                .iter()
+                .zip(b.iter())
+                .map(|(a, b)| a.compare(b))
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or_else(|| a.len().cmp(&b.len())),
+            (a, b) => a.to_string().cmp(&b.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for MetaValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::String(s) | Self::Raw(s) => f.write_str(s),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Integer(i) => write!(f, "{}", i),
+            Self::Float(x) => write!(f, "{:?}", x),
+            Self::Date(d) => write!(f, "{}", d.format(DATE_FMT)),
+            Self::DateTime(d) => f.write_str(&d.to_rfc3339()),
+            Self::List(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, "{} ", META_TAG_SEPARATOR)?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                Ok(())
+            }
+            Self::Table(_) => write!(f, "{}", json::JsonValue::from(self)),
+        }
+    }
+}
+
+/// Used to send the metadata to the external processor.
+impl From<&MetaValue> for json::JsonValue {
+    fn from(value: &MetaValue) -> Self {
+        match value {
+            MetaValue::String(s) => s.clone().into(),
+            // Lists are sent as such, but anything else keeps the text it was written with.
+            MetaValue::Raw(s) => match value.resolved().as_ref() {
+                list @ MetaValue::List(_) => list.into(),
+                _ => s.clone().into(),
+            },
+            MetaValue::Bool(b) => (*b).into(),
+            MetaValue::Integer(i) => (*i).into(),
+            MetaValue::Float(x) => (*x).into(),
+            MetaValue::Date(d) => d.format(DATE_FMT).to_string().into(),
+            MetaValue::DateTime(d) => d.to_rfc3339().into(),
+            MetaValue::List(items) => {
+                json::JsonValue::Array(items.iter().map(json::JsonValue::from).collect())
+            }
+            MetaValue::Table(table) => {
+                let mut object = json::JsonValue::new_object();
+                for (key, value) in table {
+                    object[key.as_str()] = value.into();
+                }
+                object
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn infer() {
+        assert_eq!(MetaValue::infer("true"), MetaValue::Bool(true));
+        assert_eq!(MetaValue::infer("-12"), MetaValue::Integer(-12));
+        assert_eq!(MetaValue::infer("1.0"), MetaValue::Float(1.0));
+        assert_eq!(MetaValue::infer("1.0").to_string(), "1.0");
+        assert_eq!(
+            MetaValue::infer("2020-02-20"),
+            MetaValue::Date(NaiveDate::from_ymd_opt(2020, 2, 20).unwrap())
+        );
+        assert!(matches!(
+            MetaValue::infer("2020-02-20T10:00:00+02:00"),
+            MetaValue::DateTime(_)
+        ));
+        assert_eq!(
+            MetaValue::infer("Hello, world!"),
+            MetaValue::String("Hello, world!".to_owned())
+        );
+        assert_eq!(MetaValue::infer("inf"), MetaValue::String("inf".to_owned()));
+        assert_eq!(MetaValue::infer("[a, 2]").as_list(), ["a", "2"]);
+        assert_eq!(MetaValue::infer("rust, ssg").as_list(), ["rust", "ssg"]);
+    }
+
+    #[test]
+    fn infer_keeps_text() {
+        for text in ["007", "+44", "1e3", "1.50", "[RFC] Foo [v2]", "[a, b]"] {
+            assert_eq!(MetaValue::infer(text).to_string(), text);
+        }
+        assert_eq!(
+            MetaValue::infer("007").compare(&MetaValue::Integer(7)),
+            Ordering::Equal
+        );
+        assert_eq!(
+            MetaValue::infer("1e3").compare(&MetaValue::infer("999")),
+            Ordering::Greater
+        );
+        assert!(MetaValue::infer("[a, b]").matches("b"));
+        assert_eq!(
+            MetaValue::infer("[RFC] Foo [v2]").as_list(),
+            ["[RFC] Foo [v2]"]
+        );
+    }
+
+    #[test]
+    fn compare() {
+        assert_eq!(
+            MetaValue::Integer(9).compare(&MetaValue::Integer(10)),
+            Ordering::Less
+        );
+        assert_eq!(
+            MetaValue::Integer(2).compare(&MetaValue::Float(1.5)),
+            Ordering::Greater
+        );
+        assert!(MetaValue::infer("[rust, ssg]").matches("ssg"));
+        assert!(MetaValue::infer("3").matches("3.0"));
+        assert!(MetaValue::infer("2020-02-20T10:00:00+02:00").matches("2020-02-20"));
+        assert!(!MetaValue::infer("2020-02-20T10:00:00+02:00").matches("2020-02-21"));
+    }
+}
@@ -1,46 +1,66 @@
 use crate::config::{
-    Config, DATE_FMT, META_KEY_CATEGORY, META_KEY_CREATION_DATE, META_KEY_MODIFIED_DATE,
-    META_KEY_TAGS, META_KEY_TEMPLATE, META_KEY_TITLE, META_TAG_SEPARATOR, META_VALUE_SEPARATOR,
-    SOURCE_META_KEY,
+    Config, UrlStyle, DATETIME_FMT, DATE_FMT, INDEX_FILE_STEM, META_KEY_ALIASES, META_KEY_AUTHOR,
+    META_KEY_CATEGORY, META_KEY_CREATION_DATE, META_KEY_MODIFIED_DATE, META_KEY_PERMALINK,
+    META_KEY_SERIES, META_KEY_SLUG, META_KEY_SUMMARY, META_KEY_TAGS, META_KEY_TEMPLATE,
+    META_KEY_TITLE, META_VALUE_SEPARATOR, SOURCE_META_KEY,
 };
+use crate::meta::MetaValue;
 
-use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag};
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
 use std::collections::HashMap;
 use std::fs;
 use std::io;
-use std::path::{Path, PathBuf};
-use std::time::UNIX_EPOCH;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
 
-use chrono::offset::Local;
-use chrono::{Date, NaiveDate, NaiveDateTime, TimeZone};
+use chrono::offset::{Local, Utc};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, SubsecRound, TimeDelta, TimeZone};
 
 const ZWNBSP: &str = "\u{FEFF}";
 
 /// Represents a Markdown Post that will be converted into HTML.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Post {
     /// Source file path.
     pub path: PathBuf,
     /// Markdown content with the metadata removed.
     pub markdown: String,
+    /// Amount of lines removed from the markdown along with the metadata.
+    pub line_offset: usize,
     /// Metadata key-value pairs extracted from the file.
-    pub meta: HashMap<String, String>,
+    pub meta: HashMap<String, MetaValue>,
     /// Post's title (from the metadata, first heading, or file name).
     pub title: String,
-    /// Post's date (from the metadata or filesystem creation date).
-    pub date: Date<Local>,
-    /// Post's last-modified date (from the metadata or filesystem modified date).
-    pub updated: Date<Local>,
+    /// Post's date (from the metadata, first git commit, or filesystem creation date).
+    pub date: DateTime<FixedOffset>,
+    /// Post's last-modified date (from the metadata, last git commit, or filesystem modified date).
+    pub updated: DateTime<FixedOffset>,
     /// Post's category (from the metadata).
     pub category: String,
     /// Post's tags (from the metadata).
     pub tags: Vec<String>,
+    /// Post's author (from the metadata or configuration).
+    pub author: Option<String>,
+    /// Post's summary (from the metadata).
+    pub summary: Option<String>,
     /// Post's template (from the metadata).
     pub template: Option<PathBuf>,
-    /// Post's absolute URI within a root.
+    /// Path where the post will be generated, relative to the source root.
+    pub dist_path: PathBuf,
+    /// Paths where redirects to the post will be generated, relative to the source root (from the metadata).
+    pub aliases: Vec<PathBuf>,
+    /// Post's absolute URI within a root (ending with a slash for pretty URLs).
     pub uri: String,
     /// Headings that make up the Table of Contents along with heading depth.
     pub toc: Vec<(String, u8)>,
+    /// Amount of words in the text (and code blocks, if configured).
+    pub word_count: usize,
+    /// Estimated minutes needed to read the post, rounded up.
+    pub reading_time: usize,
+    /// Series of posts this post is a part of (from the metadata).
+    pub series: Option<String>,
+    /// Which part of its series the post is, and how many parts there are (set once all posts are known).
+    pub series_part: Option<(usize, usize)>,
 }
 
 impl Post {
@@ -54,23 +74,33 @@ impl Post {
         let mut markdown = fs::read_to_string(&path)?.replace(ZWNBSP, "");
 
         let mut meta = HashMap::new();
-        if let Some((Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))), start_range)) =
-            Parser::new(&markdown).into_offset_iter().next()
+        let mut line_offset = 0;
+        if let Some((format, range, contents)) = crate::front_matter::find(&markdown) {
+            meta = crate::front_matter::parse(format, &markdown[contents]).map_err(|e| {
+                io::Error::new(e.kind(), format!("invalid front matter: {}: {:?}", e, path))
+            })?;
+            line_offset = markdown[range.clone()].matches('\n').count();
+            markdown.replace_range(range, "");
+        } else if let Some((
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))),
+            start_range,
+        )) = Parser::new(&markdown).into_offset_iter().next()
         {
             if lang.as_ref() == SOURCE_META_KEY {
-                meta.extend(markdown[start_range.clone()].lines().filter_map(|line| {
-                    let mut kv = line.splitn(2, META_VALUE_SEPARATOR);
-                    kv.next()
-                        .zip(kv.next())
-                        .map(|(k, v)| (k.trim().to_owned(), v.trim().to_owned()))
-                }));
+                // Skip the line with the opening fence (the closing one ends the parsing).
+                let block = markdown[start_range.clone()]
+                    .split_once('\n')
+                    .map_or("", |(_, rest)| rest);
+                let first_line = markdown[..start_range.start].matches('\n').count() + 2;
+                meta = parse_meta_block(&path, first_line, block);
+                line_offset = markdown[start_range.clone()].matches('\n').count();
                 markdown.replace_range(start_range, "");
             }
         }
 
         let title = meta
             .get(META_KEY_TITLE)
-            .cloned()
+            .map(MetaValue::to_string)
             .or_else(|| {
                 let mut wait_title = false;
                 Parser::new(&markdown).find_map(|event| {
@@ -92,69 +122,142 @@ impl Post {
                     .to_owned()
             });
 
+        let file_stem = path
+            .file_stem()
+            .unwrap()
+            .to_str()
+            .expect("bad md file name");
+        let (name_date, file_stem) = match split_date_prefix(file_stem) {
+            Some((date, rest)) if config.date_prefix => (Some(date), rest),
+            _ => (None, file_stem),
+        };
+
         let metadata = fs::metadata(&path)?;
-        let date = meta
-            .get(META_KEY_CREATION_DATE)
-            .and_then(|date| NaiveDate::parse_from_str(date, DATE_FMT).ok())
+        let git_dates = config.git_dates.as_ref().and_then(|git| git.get(&path));
+        let meta_date = |key| {
+            let value = meta.get(key)?;
+            let date = meta_datetime(config, value);
+            if date.is_none() {
+                eprintln!(
+                    "warning: could not understand {} {:?}, it will be ignored: {}",
+                    key,
+                    value.to_string(),
+                    path.display()
+                );
+            }
+            date
+        };
+        let date = meta_date(META_KEY_CREATION_DATE)
+            .or_else(|| local_datetime(config, name_date?.and_hms_opt(0, 0, 0)?))
+            .or_else(|| git_dates.map(|(created, _)| site_datetime(config, created)))
             .or_else(|| {
                 metadata
                     .created()
                     .ok()
-                    .and_then(|date| date.duration_since(UNIX_EPOCH).ok())
-                    .map(|duration| {
-                        NaiveDateTime::from_timestamp(
-                            duration.as_secs() as i64,
-                            duration.subsec_nanos(),
-                        )
-                        .date()
-                    })
+                    .map(|date| site_datetime(config, date))
             })
-            .and_then(|date| Local.from_local_date(&date).latest())
-            .unwrap_or_else(|| Local::now().date());
+            .unwrap_or_else(|| site_datetime(config, SystemTime::now()));
 
-        let updated = meta
-            .get(META_KEY_MODIFIED_DATE)
-            .and_then(|date| NaiveDate::parse_from_str(date, DATE_FMT).ok())
+        let updated = meta_date(META_KEY_MODIFIED_DATE)
+            .or_else(|| git_dates.map(|(_, modified)| site_datetime(config, modified)))
             .or_else(|| {
                 metadata
                     .modified()
                     .ok()
-                    .and_then(|date| date.duration_since(UNIX_EPOCH).ok())
-                    .map(|duration| {
-                        NaiveDateTime::from_timestamp(
-                            duration.as_secs() as i64,
-                            duration.subsec_nanos(),
-                        )
-                        .date()
-                    })
+                    .map(|date| site_datetime(config, date))
             })
-            .and_then(|date| Local.from_local_date(&date).latest())
             .unwrap_or(date);
 
-        let category = meta.get(META_KEY_CATEGORY).cloned().unwrap_or_else(|| {
-            path.parent()
-                .expect("post file had no parent")
-                .file_name()
-                .expect("post parent had no name")
-                .to_str()
-                .expect("post parent had non-utf8 name")
-                .to_owned()
-        });
+        let category = meta
+            .get(META_KEY_CATEGORY)
+            .map(MetaValue::to_string)
+            .unwrap_or_else(|| {
+                path.parent()
+                    .expect("post file had no parent")
+                    .file_name()
+                    .expect("post parent had no name")
+                    .to_str()
+                    .expect("post parent had non-utf8 name")
+                    .to_owned()
+            });
 
         let tags = meta
             .get(META_KEY_TAGS)
-            .map(|tags| {
-                tags.split(META_TAG_SEPARATOR)
-                    .map(|s| s.trim().to_owned())
-                    .collect()
-            })
+            .map(MetaValue::as_list)
             .unwrap_or_else(Vec::new);
 
+        let author = meta
+            .get(META_KEY_AUTHOR)
+            .map(MetaValue::to_string)
+            .or_else(|| config.author.clone());
+
+        let summary = meta.get(META_KEY_SUMMARY).map(MetaValue::to_string);
+
+        let series = meta.get(META_KEY_SERIES).map(MetaValue::to_string);
+
         let template = meta
             .get(META_KEY_TEMPLATE)
-            .map(|s| crate::utils::get_abs_path(root, &path, s));
+            .map(|s| crate::utils::get_abs_path(root, &path, &s.to_string()));
 
-        let uri = crate::utils::path_to_uri(root, &path.with_extension(&config.dist_ext));
+        let slug = meta
+            .get(META_KEY_SLUG)
+            .map(MetaValue::to_string)
+            .unwrap_or_else(|| file_stem.to_owned());
+        if slug.is_empty() || slug.contains('/') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid slug {:?}: {:?}", slug, path),
+            ));
+        }
+
+        let (dist_path, uri) = match meta.get(META_KEY_PERMALINK) {
+            Some(permalink) => {
+                // Whether there's an extension is decided before placeholders bring their own dots.
+                let has_extension = permalink_has_extension(&permalink.to_string());
+                let permalink = permalink
+                    .to_string()
+                    .replace(":year", &date.format("%Y").to_string())
+                    .replace(":month", &date.format("%m").to_string())
+                    .replace(":day", &date.format("%d").to_string())
+                    .replace(":category", &category)
+                    .replace(":slug", &slug);
+
+                let target = crate::utils::get_abs_path(root, &path, &permalink);
+                if target.components().any(|c| c == Component::ParentDir) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "permalink {:?} cannot contain \"..\": {:?}",
+                            permalink, path
+                        ),
+                    ));
+                }
+
+                if permalink.ends_with('/') {
+                    let dist_path = target.join(format!("{}.{}", INDEX_FILE_STEM, config.dist_ext));
+                    let uri = crate::utils::path_to_uri(root, &dist_path);
+                    (dist_path, crate::utils::uri_dir(&uri).to_owned())
+                } else if has_extension {
+                    let uri = crate::utils::path_to_uri(root, &target);
+                    (target, uri)
+                } else {
+                    output_location(config, root, target)
+                }
+            }
+            None => output_location(config, root, path.with_file_name(&slug)),
+        };
+
+        let aliases = meta
+            .get(META_KEY_ALIASES)
+            .map(|aliases| {
+                aliases
+                    .as_list()
+                    .iter()
+                    .map(|alias| crate::redirect::alias_path(config, root, &path, alias))
+                    .collect::<io::Result<Vec<_>>>()
+            })
+            .transpose()?
+            .unwrap_or_else(Vec::new);
 
         let toc = {
             let mut toc_depth = None;
@@ -172,20 +275,347 @@ impl Post {
                 .collect()
         };
 
+        let word_count = count_words(&markdown, config.count_code_words);
+        let reading_time = word_count.div_ceil(config.words_per_minute);
+
         Ok(Self {
             path,
             markdown,
+            line_offset,
             meta,
             title,
             date,
             updated,
             category,
             tags,
+            author,
+            summary,
             template,
+            dist_path,
+            aliases,
             uri,
             toc,
+            word_count,
+            reading_time,
+            series,
+            series_part: None,
+        })
+    }
+
+    /// Value of the metadata key, which may be a dot-separated path into tables (as in `author.name`).
+    pub fn meta_value(&self, key: &str) -> Option<&MetaValue> {
+        self.meta.get(key).or_else(|| {
+            let (key, path) = key.split_once('.')?;
+            self.meta.get(key)?.get_path(path)
+        })
+    }
+}
+
+/// Parse the `key = value` lines inside the meta code block, starting at the given line of the file.
+///
+/// Values may continue in the following indented lines (which are joined with a space), or be quoted
+/// as in template rules to span several lines verbatim. Lines which can't be understood are reported.
+fn parse_meta_block(path: &Path, first_line: usize, block: &str) -> HashMap<String, MetaValue> {
+    // The closing fence ends the block.
+    let len = block
+        .split_inclusive('\n')
+        .take_while(|line| {
+            let line = line.trim_start();
+            !line.starts_with("```") && !line.starts_with("~~~")
+        })
+        .map(str::len)
+        .sum();
+    let block = &block[..len];
+
+    let mut meta = HashMap::new();
+    // Key and unquoted value, which may still continue in the next lines.
+    let mut pending: Option<(String, String)> = None;
+    let mut line_number = first_line;
+    let mut offset = 0;
+
+    while offset < block.len() {
+        let line_start = offset;
+        let line_end = block[offset..]
+            .find('\n')
+            .map_or(block.len(), |i| offset + i);
+        let line = &block[line_start..line_end];
+        let number = line_number;
+        line_number += 1;
+        offset = line_end + 1;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+        // Indented lines continue the value before them, unless they're a `key = value` of their own (indented keys
+        // have always been accepted).
+        if line.starts_with(char::is_whitespace) && !line.contains(META_VALUE_SEPARATOR) {
+            if let Some((_, value)) = pending.as_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+                continue;
+            }
+        }
+
+        if let Some((key, value)) = pending.take() {
+            meta.insert(key, MetaValue::infer(&value));
+        }
+
+        let (key, value) = match line.split_once(META_VALUE_SEPARATOR) {
+            Some((key, value)) if !key.trim().is_empty() => (key.trim().to_owned(), value),
+            _ => {
+                eprintln!(
+                    "warning: malformed meta line (expected `key {} value`): {}:{}",
+                    META_VALUE_SEPARATOR,
+                    path.display(),
+                    number
+                );
+                continue;
+            }
+        };
+
+        if !value.trim_start().starts_with('"') {
+            pending = Some((key, value.trim().to_owned()));
+            continue;
+        }
+
+        // Quoted values may span several lines, so parse them out of the rest of the block.
+        let start = line_end - value.trim_start().len();
+        let mut parsing = &block[start..];
+        let quoted = crate::utils::parse_next_value(&mut parsing).unwrap_or_default();
+        let end = block.len() - parsing.len();
+        let closed = end > start + 1 && block[..end].ends_with('"');
+
+        let end_number = number + block[line_start..end].matches('\n').count();
+        let line_end = block[end..].find('\n').map_or(block.len(), |i| end + i);
+        if !closed {
+            eprintln!(
+                "warning: meta value is missing its closing quote: {}:{}",
+                path.display(),
+                number
+            );
+        } else if !block[end..line_end].trim().is_empty() {
+            eprintln!(
+                "warning: unexpected text after quoted meta value: {}:{}",
+                path.display(),
+                end_number
+            );
+        }
+        meta.insert(key, MetaValue::String(quoted));
+        line_number = end_number + 1;
+        offset = line_end + 1;
+    }
+
+    if let Some((key, value)) = pending {
+        meta.insert(key, MetaValue::infer(&value));
+    }
+    meta
+}
+
+/// Count the words in the text of the markdown (and its code blocks, if wanted).
+///
+/// Text is split into several events by inline elements and smart punctuation (`don't` becomes
+/// `don`, `’` and `t`), so the text of every block is joined before splitting it into words.
+fn count_words(markdown: &str, count_code: bool) -> usize {
+    let mut text = String::new();
+    let mut in_code = false;
+    for event in Parser::new_ext(markdown, Options::all()) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code = true;
+                text.push(' ');
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                in_code = false;
+                text.push(' ');
+            }
+            Event::Text(s) if !in_code || count_code => text.push_str(&s),
+            Event::Code(s) => text.push_str(&s),
+            // Inline elements may be in the middle of a word, so only blocks separate words.
+            Event::Start(tag) | Event::End(tag)
+                if !matches!(
+                    tag,
+                    Tag::Emphasis
+                        | Tag::Strong
+                        | Tag::Strikethrough
+                        | Tag::Link(..)
+                        | Tag::Image(..)
+                ) =>
+            {
+                text.push(' ')
+            }
+            Event::SoftBreak | Event::HardBreak | Event::Rule => text.push(' '),
+            _ => {}
+        }
+    }
+    text.split_whitespace().count()
+}
+
+/// Split the `YYYY-MM-DD-` prefix from a file stem (as in `2020-02-20-hello`) into a date and the rest.
+fn split_date_prefix(stem: &str) -> Option<(NaiveDate, &str)> {
+    let (date, rest) = stem.split_at(stem.char_indices().nth(10)?.0);
+    let rest = rest.strip_prefix('-').filter(|rest| !rest.is_empty())?;
+    Some((NaiveDate::parse_from_str(date, DATE_FMT).ok()?, rest))
+}
+
+/// Date and time in a metadata value, which may be an RFC 3339 timestamp, a date and time without
+/// offset, or a plain date (at midnight), the last two being in the site's time zone.
+fn meta_datetime(config: &Config, value: &MetaValue) -> Option<DateTime<FixedOffset>> {
+    let naive = match value {
+        MetaValue::DateTime(datetime) => return Some(*datetime),
+        MetaValue::Date(date) => date.and_hms_opt(0, 0, 0)?,
+        value => {
+            let value = value.to_string();
+            if let Ok(datetime) = DateTime::parse_from_rfc3339(&value) {
+                return Some(datetime);
+            }
+            NaiveDateTime::parse_from_str(&value, DATETIME_FMT)
+                .ok()
+                .or_else(|| {
+                    NaiveDate::parse_from_str(&value, DATE_FMT)
+                        .ok()?
+                        .and_hms_opt(0, 0, 0)
+                })?
+        }
+    };
+    local_datetime(config, naive)
+}
+
+/// Interpret a date and time without offset in the site's time zone.
+fn local_datetime(config: &Config, naive: NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+    match config.timezone {
+        Some(tz) => resolve_local(&tz, naive),
+        None => resolve_local(&Local, naive),
+    }
+}
+
+/// Interpret a date and time without offset in the time zone, taking the earliest if it's ambiguous.
+///
+/// Times skipped when daylight saving time starts (which may be midnight, and thus any plain date in
+/// some zones) are moved forward past the gap instead of being dropped.
+fn resolve_local<Z: TimeZone>(tz: &Z, naive: NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+    // Gaps are at most a few hours long and start and end at quarters of an hour.
+    (0..=24 * 4)
+        .find_map(|i| {
+            tz.from_local_datetime(&(naive + TimeDelta::minutes(15 * i)))
+                .earliest()
         })
+        .map(|d| d.fixed_offset())
+}
+
+/// Date and time from the filesystem or git in the site's time zone (to the second).
+fn site_datetime<T: Into<DateTime<Utc>>>(config: &Config, time: T) -> DateTime<FixedOffset> {
+    let utc = time.into().trunc_subsecs(0);
+    match config.timezone {
+        Some(tz) => utc.with_timezone(&tz).fixed_offset(),
+        None => utc.with_timezone(&Local).fixed_offset(),
     }
 }
 
+/// Where the post with the given path (without extension) is generated, and its URI, according to the URL style.
+fn output_location(config: &Config, root: &Path, stem: PathBuf) -> (PathBuf, String) {
+    let is_index = stem.file_name().and_then(|s| s.to_str()) == Some(INDEX_FILE_STEM);
+    match config.url_style {
+        UrlStyle::pretty if !is_index => {
+            let dist_path = stem.join(format!("{}.{}", INDEX_FILE_STEM, config.dist_ext));
+            let uri = crate::utils::path_to_uri(root, &dist_path);
+            (dist_path, crate::utils::uri_dir(&uri).to_owned())
+        }
+        UrlStyle::pretty => {
+            let dist_path = with_dist_ext(stem, &config.dist_ext);
+            let uri = crate::utils::path_to_uri(root, &dist_path);
+            (dist_path, crate::utils::uri_dir(&uri).to_owned())
+        }
+        UrlStyle::file => {
+            let dist_path = with_dist_ext(stem, &config.dist_ext);
+            let uri = crate::utils::path_to_uri(root, &dist_path);
+            (dist_path, uri)
+        }
+    }
+}
+
+/// Add the extension to the path, keeping any dots already in its file name (as in `release-1.0`).
+fn with_dist_ext(path: PathBuf, ext: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(ext);
+    path.with_file_name(name)
+}
+
+/// Whether the last segment of the permalink template has an extension of its own (as in `/:slug.htm`).
+fn permalink_has_extension(permalink: &str) -> bool {
+    let name = permalink.rsplit('/').next().unwrap_or_default();
+    Path::new(name).extension().is_some()
+}
+
 // TODO add back old Post tests?
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meta_block() {
+        let block = "title = Hello, world!\ndescription = A long\n  description\n\nabstract = \"First.\n\nSecond with \\\"quotes\\\".\"\nbroken line\ntags = [a, b]\n```\nafter = end\n";
+        let meta = parse_meta_block(Path::new("post.md"), 2, block);
+        assert_eq!(meta["title"].to_string(), "Hello, world!");
+        assert_eq!(meta["description"].to_string(), "A long description");
+        assert_eq!(
+            meta["abstract"],
+            MetaValue::String("First.\n\nSecond with \"quotes\".".to_owned())
+        );
+        assert_eq!(meta["tags"].as_list(), ["a", "b"]);
+        assert!(!meta.contains_key("after"));
+
+        let meta = parse_meta_block(
+            Path::new("post.md"),
+            2,
+            "  title = Foo\n  date = 2020-02-20\n",
+        );
+        assert_eq!(meta["title"].to_string(), "Foo");
+        assert_eq!(meta["date"].to_string(), "2020-02-20");
+    }
+
+    #[test]
+    fn word_count() {
+        assert_eq!(count_words("don't stop", false), 2);
+        assert_eq!(count_words("it's \"quoted\" text", false), 3);
+        assert_eq!(count_words("*em*phasis and **bo**ld `code`", false), 4);
+        assert_eq!(count_words("# Title\nText\nmore\n\n- one\n- two", false), 5);
+        assert_eq!(count_words("Text\n\n```\nlet x = 1;\n```\n", false), 1);
+        assert_eq!(count_words("Text\n\n```\nlet x = 1;\n```\n", true), 5);
+    }
+
+    #[test]
+    fn dotted_slug() {
+        assert_eq!(
+            with_dist_ext(PathBuf::from("blog/release-1.0"), "html"),
+            PathBuf::from("blog/release-1.0.html")
+        );
+        assert!(!permalink_has_extension("/:year/:slug"));
+        assert!(permalink_has_extension("/:year/:slug.htm"));
+        assert!(!permalink_has_extension("/v1.0/:slug"));
+    }
+
+    #[test]
+    fn dst_gap() {
+        // Daylight saving time started at midnight in São Paulo on 2018-11-04.
+        let midnight = NaiveDate::from_ymd_opt(2018, 11, 4)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let resolved = resolve_local(&chrono_tz::America::Sao_Paulo, midnight).unwrap();
+        assert_eq!(resolved.to_rfc3339(), "2018-11-04T01:00:00-02:00");
+    }
+
+    #[test]
+    fn date_prefix() {
+        assert_eq!(
+            split_date_prefix("2020-02-20-hello"),
+            Some((NaiveDate::from_ymd_opt(2020, 2, 20).unwrap(), "hello"))
+        );
+        assert_eq!(split_date_prefix("2020-02-20"), None);
+        assert_eq!(split_date_prefix("2020-02-20-"), None);
+        assert_eq!(split_date_prefix("2020-13-20-hello"), None);
+        assert_eq!(split_date_prefix("hello"), None);
+    }
+}
@@ -0,0 +1,78 @@
+use crate::config::{Config, INDEX_FILE_STEM};
+use crate::{utils, Post};
+
+use pulldown_cmark::escape::{escape_href, escape_html};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Path where the redirect stub for the `alias` of the post at `path` is generated.
+///
+/// Aliases ending with a slash or without extension are generated as the index of that directory.
+pub fn alias_path(config: &Config, root: &Path, path: &Path, alias: &str) -> io::Result<PathBuf> {
+    let target = utils::get_abs_path(root, path, alias);
+    if target.components().any(|c| c == Component::ParentDir) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("alias {:?} cannot contain \"..\": {:?}", alias, path),
+        ));
+    }
+
+    if alias.ends_with('/') || target.extension().is_none() {
+        Ok(target.join(format!("{}.{}", INDEX_FILE_STEM, config.dist_ext)))
+    } else {
+        Ok(target)
+    }
+}
+
+/// The URI under which the redirect stub at `path` is served.
+pub fn alias_uri(config: &Config, root: &Path, path: &Path) -> String {
+    let uri = utils::path_to_uri(root, path);
+    match uri.strip_suffix(&format!("{}.{}", INDEX_FILE_STEM, config.dist_ext)) {
+        Some(dir) if dir.ends_with('/') => dir.to_owned(),
+        _ => uri,
+    }
+}
+
+/// A minimal HTML page at the `alias` path which sends the browser (and search engines) to the post.
+///
+/// The post is linked relative to the alias, and named as canonical only if the site's URL is known.
+pub fn stub_html(config: &Config, root: &Path, post: &Post, alias: &Path) -> String {
+    let mut href = String::new();
+    escape_href(
+        &mut href,
+        &utils::get_relative_uri(&alias_uri(config, root, alias), &post.uri),
+    )
+    .unwrap();
+    let mut title = String::new();
+    escape_html(&mut title, &post.title).unwrap();
+
+    let mut canonical = String::new();
+    if let Some(base_url) = config.base_url.as_ref() {
+        canonical.push_str("<link rel=\"canonical\" href=\"");
+        escape_href(&mut canonical, &format!("{}{}", base_url, post.uri)).unwrap();
+        canonical.push_str("\">\n");
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         {canonical}<meta http-equiv=\"refresh\" content=\"0; url={href}\">\n\
+         </head>\n<body>\n<p>This page has moved to <a href=\"{href}\">{title}</a>.</p>\n</body>\n</html>\n",
+        title = title,
+        canonical = canonical,
+        href = href,
+    )
+}
+
+/// Rules in the `_redirects` format understood by some hosts, one permanent redirect per alias.
+pub fn redirect_rules(config: &Config, root: &Path, posts: &[Post]) -> String {
+    let mut rules = String::new();
+    for post in posts {
+        for alias in post.aliases.iter() {
+            rules.push_str(&alias_uri(config, root, alias));
+            rules.push(' ');
+            rules.push_str(&post.uri);
+            rules.push_str(" 301\n");
+        }
+    }
+    rules
+}
@@ -0,0 +1,112 @@
+use crate::config::META_KEY_SERIES_ORDER;
+use crate::Post;
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Compare two parts of a series by their `series_order` (those without one go last), and then by date.
+fn compare_parts(a: &Post, b: &Post) -> Ordering {
+    match (
+        a.meta.get(META_KEY_SERIES_ORDER),
+        b.meta.get(META_KEY_SERIES_ORDER),
+    ) {
+        (Some(a), Some(b)) => a.compare(b),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    }
+    .then_with(|| a.date.cmp(&b.date))
+    .then_with(|| a.path.cmp(&b.path))
+}
+
+/// All the posts in the same series as the post (itself included) in reading order, if it's in one.
+pub fn series_posts<'a>(post: &Post, posts: &'a [Post]) -> Vec<&'a Post> {
+    let series = match post.series.as_ref() {
+        Some(series) => series,
+        None => return Vec::new(),
+    };
+
+    let mut parts = posts
+        .iter()
+        .filter(|other| other.series.as_ref() == Some(series))
+        .collect::<Vec<_>>();
+    parts.sort_by(|a, b| compare_parts(a, b));
+    parts
+}
+
+/// Set which part of its series every post is, and how many parts the series has.
+pub fn number_parts(posts: &mut [Post]) {
+    // Indices of the posts in every series, sorted once per series.
+    let mut series = HashMap::<&str, Vec<usize>>::new();
+    for (index, post) in posts.iter().enumerate() {
+        if let Some(name) = post.series.as_deref() {
+            series.entry(name).or_default().push(index);
+        }
+    }
+
+    let mut parts = vec![None; posts.len()];
+    for indices in series.values_mut() {
+        indices.sort_by(|&a, &b| compare_parts(&posts[a], &posts[b]));
+        for (part, &index) in indices.iter().enumerate() {
+            parts[index] = Some((part + 1, indices.len()));
+        }
+    }
+
+    for (post, part) in posts.iter_mut().zip(parts) {
+        post.series_part = part;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::MetaValue;
+    use chrono::DateTime;
+    use std::path::PathBuf;
+
+    fn part(path: &str, series: &str, order: Option<&str>, date: &str) -> Post {
+        let mut post = Post {
+            path: PathBuf::from(path),
+            series: Some(series.to_owned()),
+            date: DateTime::parse_from_rfc3339(date).unwrap(),
+            ..Post::default()
+        };
+        if let Some(order) = order {
+            post.meta
+                .insert(META_KEY_SERIES_ORDER.to_owned(), MetaValue::infer(order));
+        }
+        post
+    }
+
+    #[test]
+    fn order() {
+        let posts = [
+            part("e.md", "a", None, "2020-01-01T00:00:00Z"),
+            part("d.md", "a", None, "2020-01-01T00:00:00Z"),
+            part("c.md", "a", None, "2019-01-01T00:00:00Z"),
+            part("b.md", "a", Some("10"), "2021-01-01T00:00:00Z"),
+            part("a.md", "a", Some("2"), "2022-01-01T00:00:00Z"),
+            part("x.md", "b", Some("1"), "2000-01-01T00:00:00Z"),
+        ];
+        let paths = series_posts(&posts[0], &posts)
+            .iter()
+            .map(|post| post.path.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["a.md", "b.md", "c.md", "d.md", "e.md"]);
+        assert!(series_posts(&Post::default(), &posts).is_empty());
+    }
+
+    #[test]
+    fn number() {
+        let mut posts = vec![
+            part("b.md", "a", None, "2020-01-01T00:00:00Z"),
+            part("x.md", "b", None, "2020-01-01T00:00:00Z"),
+            part("a.md", "a", Some("1"), "2021-01-01T00:00:00Z"),
+            Post::default(),
+        ];
+        number_parts(&mut posts);
+        let parts = posts
+            .iter()
+            .map(|post| post.series_part)
+            .collect::<Vec<_>>();
+        assert_eq!(parts, [Some((2, 2)), Some((1, 1)), Some((1, 2)), None]);
+    }
+}
@@ -0,0 +1,39 @@
+use crate::config::{META_KEY_DRAFT, META_KEY_SITEMAP};
+use crate::Post;
+
+use chrono::SecondsFormat;
+use pulldown_cmark::escape::escape_html;
+
+/// Whether the metadata key of the post is set to a true-ish value, if it is set at all.
+fn meta_flag(post: &Post, key: &str) -> Option<bool> {
+    post.meta
+        .get(key)
+        .map(|value| value.as_bool().unwrap_or(true))
+}
+
+/// Generate the `sitemap.xml` listing every post under the `base_url`.
+///
+/// Drafts and posts which opted out with `sitemap = false` are left out.
+pub fn generate_sitemap(base_url: &str, posts: &[Post]) -> String {
+    let mut posts = posts
+        .iter()
+        .filter(|post| !meta_flag(post, META_KEY_DRAFT).unwrap_or(false))
+        .filter(|post| meta_flag(post, META_KEY_SITEMAP).unwrap_or(true))
+        .collect::<Vec<_>>();
+    posts.sort_by(|a, b| a.uri.cmp(&b.uri));
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for post in posts {
+        xml.push_str("  <url>\n    <loc>");
+        escape_html(&mut xml, base_url).unwrap();
+        escape_html(&mut xml, &post.uri).unwrap();
+        xml.push_str("</loc>\n    <lastmod>");
+        xml.push_str(&post.updated.to_rfc3339_opts(SecondsFormat::Secs, false));
+        xml.push_str("</lastmod>\n  </url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
@@ -0,0 +1,296 @@
+use crate::config::{Config, CSS_BUNDLE_NAME, STYLE_FILE_EXT};
+use crate::fingerprint::Manifest;
+use crate::utils;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The CSS files found and how they should be referenced from each page.
+pub struct Styles {
+    /// URIs to the CSS files found, in the order they should be linked.
+    files: Vec<String>,
+    /// Contents of the CSS files, only loaded if they need to be bundled or inlined.
+    contents: HashMap<String, String>,
+    /// Where the generated bundles should be written.
+    destination: PathBuf,
+    bundle: bool,
+    inline_threshold: Option<u64>,
+}
+
+impl Styles {
+    /// Prepare the CSS files that have already been copied to the `destination` (and fingerprinted, if so configured).
+    pub fn new(
+        config: &Config,
+        files: Vec<String>,
+        destination: &Path,
+        manifest: &Manifest,
+    ) -> Self {
+        let mut contents = HashMap::new();
+        if config.css_bundle || config.css_inline.is_some() {
+            for uri in files.iter() {
+                let path = destination.join(manifest.get(uri).trim_start_matches('/'));
+                match fs::read_to_string(&path) {
+                    Ok(css) => {
+                        contents.insert(uri.clone(), css);
+                    }
+                    Err(e) => eprintln!("note: failed to load css file: {}: {:?}", e, path),
+                }
+            }
+        }
+
+        Self {
+            files,
+            contents,
+            destination: destination.to_path_buf(),
+            bundle: config.css_bundle,
+            inline_threshold: config.css_inline,
+        }
+    }
+
+    /// The HTML needed to load every CSS file in the same directory as `uri` or above.
+    ///
+    /// Bundles are written to the destination the first time they're needed.
+    pub fn html_for(&self, uri: &str) -> io::Result<String> {
+        let applicable = self
+            .files
+            .iter()
+            .filter(|css| uri.starts_with(&css[..css.rfind('/').unwrap()]))
+            .collect::<Vec<_>>();
+
+        let mut res = String::new();
+        if self.bundle && applicable.len() > 1 {
+            let mut bundle = String::new();
+            let mut bundle_uri = format!("/{}.{}", CSS_BUNDLE_NAME, STYLE_FILE_EXT);
+            for css in applicable {
+                if let Some(content) = self.contents.get(css) {
+                    bundle.push_str(&rewrite_urls(content, css, &bundle_uri));
+                    bundle.push('\n');
+                }
+            }
+
+            if self.should_inline(&bundle) {
+                push_style(&mut res, &rewrite_urls(&bundle, &bundle_uri, uri));
+            } else {
+                bundle_uri = format!(
+                    "/{}.{}.{}",
+                    CSS_BUNDLE_NAME,
+                    utils::content_hash(bundle.as_bytes()),
+                    STYLE_FILE_EXT
+                );
+                let path = self.destination.join(bundle_uri.trim_start_matches('/'));
+                // The name depends on the content, so an existing file is already up-to-date.
+                if !path.is_file() {
+                    fs::write(path, bundle)?;
+                }
+                push_link(&mut res, &utils::get_relative_uri(uri, &bundle_uri));
+            }
+        } else {
+            for css in applicable {
+                match self.contents.get(css) {
+                    Some(content) if self.should_inline(content) => {
+                        push_style(&mut res, &rewrite_urls(content, css, uri));
+                    }
+                    _ => push_link(&mut res, &utils::get_relative_uri(uri, css)),
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    fn should_inline(&self, css: &str) -> bool {
+        self.inline_threshold
+            .map(|threshold| css.len() as u64 <= threshold)
+            .unwrap_or(false)
+    }
+}
+
+fn push_link(res: &mut String, href: &str) {
+    res.push_str(r#"<link rel="stylesheet" type="text/css" href=""#);
+    res.push_str(href);
+    res.push_str("\">");
+}
+
+fn push_style(res: &mut String, css: &str) {
+    res.push_str("<style>");
+    res.push_str(css);
+    res.push_str("</style>");
+}
+
+/// Rewrite the relative `url()`s in the CSS found at `from` so that they work when loaded from `to`.
+pub fn rewrite_urls(css: &str, from: &str, to: &str) -> String {
+    map_urls(css, |url| {
+        if url.starts_with('/')
+            || url.starts_with('#')
+            || url.starts_with("data:")
+            || utils::is_external_uri(url)
+        {
+            None
+        } else {
+            Some(utils::get_relative_uri(to, &utils::join_uri(from, url)))
+        }
+    })
+}
+
+/// Replace the (non-empty) `url()`s and `@import` strings in the CSS for which `map` returns a new value, keeping
+/// their quotes.
+pub fn map_urls<F: FnMut(&str) -> Option<String>>(css: &str, mut map: F) -> String {
+    let mut result = String::with_capacity(css.len());
+    let mut rest = css;
+    loop {
+        let (index, is_import) = match (rest.find("url("), rest.find("@import")) {
+            (Some(url), Some(import)) if import < url => (import, true),
+            (Some(url), _) => (url, false),
+            (None, Some(import)) => (import, true),
+            (None, None) => break,
+        };
+
+        if is_import {
+            let start = index + "@import".len();
+            result.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            // `@import url(...)` is handled like any other `url()`, but the URL may also be a plain string.
+            let quoted = rest.trim_start();
+            let quote = match quoted.chars().next() {
+                Some(q) if q == '"' || q == '\'' => q,
+                _ => continue,
+            };
+            let end = match quoted[1..].find(quote) {
+                Some(end) => 1 + end,
+                None => break,
+            };
+
+            let value_start = rest.len() - quoted.len() + 1;
+            result.push_str(&rest[..value_start]);
+            let url = &quoted[1..end];
+            match Some(url).filter(|url| !url.is_empty()).and_then(&mut map) {
+                Some(url) => result.push_str(&url),
+                None => result.push_str(url),
+            }
+            rest = &quoted[end..];
+            continue;
+        }
+
+        let start = index + "url(".len();
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.find(')') {
+            Some(end) => end,
+            None => break,
+        };
+
+        let value = rest[..end].trim();
+        let (quote, url) = match value.chars().next() {
+            Some(q) if (q == '"' || q == '\'') && value.len() >= 2 && value.ends_with(q) => {
+                (&value[..1], &value[1..value.len() - 1])
+            }
+            _ => ("", value),
+        };
+
+        match Some(url).filter(|url| !url.is_empty()).and_then(&mut map) {
+            Some(url) => {
+                result.push_str(quote);
+                result.push_str(&url);
+                result.push_str(quote);
+            }
+            None => result.push_str(&rest[..end]),
+        }
+        rest = &rest[end..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Remove the comments and unnecessary whitespace from the CSS.
+pub fn minify_css(css: &str) -> String {
+    const TIGHT: &[u8] = b"{};,>";
+
+    let bytes = css.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut pending_space = false;
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index];
+        if byte == b'/' && bytes.get(index + 1) == Some(&b'*') {
+            index = match css[index + 2..].find("*/") {
+                Some(i) => index + 2 + i + 2,
+                None => bytes.len(),
+            };
+            pending_space = true;
+            continue;
+        }
+
+        if byte.is_ascii_whitespace() {
+            pending_space = true;
+            index += 1;
+            continue;
+        }
+
+        if pending_space {
+            pending_space = false;
+            let prev_tight = result
+                .last()
+                .map(|b| TIGHT.contains(b) || *b == b':')
+                .unwrap_or(true);
+            if !prev_tight && !TIGHT.contains(&byte) {
+                result.push(b' ');
+            }
+        }
+
+        if byte == b'"' || byte == b'\'' {
+            let start = index;
+            index += 1;
+            while index < bytes.len() && bytes[index] != byte {
+                if bytes[index] == b'\\' {
+                    index += 1;
+                }
+                index += 1;
+            }
+            index = (index + 1).min(bytes.len());
+            result.extend_from_slice(&bytes[start..index]);
+            continue;
+        }
+
+        if byte == b'}' && result.last() == Some(&b';') {
+            result.pop();
+        }
+        result.push(byte);
+        index += 1;
+    }
+
+    String::from_utf8(result).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrite() {
+        let css = "a { background: url(\"img/a.png\"); } b { background: url( /b.png ) }";
+        assert_eq!(
+            rewrite_urls(css, "/blog/style.css", "/bundle.css"),
+            "a { background: url(\"blog/img/a.png\"); } b { background: url( /b.png ) }"
+        );
+        assert_eq!(
+            rewrite_urls(
+                "url(../a.png) url(data:x) url(https://x.org/a.png)",
+                "/blog/style.css",
+                "/blog/post/index.html"
+            ),
+            "url(../../a.png) url(data:x) url(https://x.org/a.png)"
+        );
+    }
+
+    #[test]
+    fn minify() {
+        let css = "/* comment */\na :hover > b,\n c {\n  color: red;\n  content: \"a  ;  b\";\n}\n";
+        assert_eq!(
+            minify_css(css),
+            "a :hover>b,c{color:red;content:\"a  ;  b\"}"
+        );
+    }
+}
@@ -1,11 +1,18 @@
 use crate::config::{
-    INCLUDE_RAW_EXTENSIONS, META_KEY_CATEGORY, META_KEY_CREATION_DATE, META_KEY_MODIFIED_DATE,
-    META_KEY_TAGS, META_KEY_TEMPLATE, META_KEY_TITLE, TEMPLATE_CLOSE_MARKER, TEMPLATE_OPEN_MARKER,
+    Config, INCLUDE_RAW_EXTENSIONS, META_KEY_CATEGORY, META_KEY_CREATION_DATE,
+    META_KEY_MODIFIED_DATE, META_KEY_READING_TIME, META_KEY_SERIES_LENGTH, META_KEY_SERIES_PART,
+    META_KEY_TAGS, META_KEY_TEMPLATE, META_KEY_TITLE, META_KEY_WORD_COUNT, TEMPLATE_CLOSE_MARKER,
+    TEMPLATE_OPEN_MARKER,
 };
-use crate::{utils, AdaptorExt as _, Post};
+use crate::media::Image;
+use crate::meta::MetaValue;
+use crate::style::Styles;
+use crate::{series, utils, AdaptorExt as _, Post};
 
+use chrono::{DateTime, FixedOffset, NaiveTime};
 use pulldown_cmark::{self as md, Parser};
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fs;
 use std::io;
 use std::ops::Range;
@@ -17,6 +24,34 @@ const RULE_TOC: &str = "TOC";
 const RULE_LIST: &str = "LIST";
 const RULE_META: &str = "META";
 const RULE_INCLUDE: &str = "INCLUDE";
+const RULE_PREV: &str = "PREV";
+const RULE_NEXT: &str = "NEXT";
+const RULE_SERIES: &str = "SERIES";
+
+/// Render the post's Markdown into the HTML used for its contents, both in its page and in feeds.
+pub fn render_contents(
+    config: &Config,
+    root: &Path,
+    md: &Post,
+    files: &[Post],
+    images: &HashMap<String, Image>,
+) -> String {
+    let mut events: Box<dyn Iterator<Item = md::Event>> = Box::new(
+        Parser::new_ext(&md.markdown, md::Options::all())
+            .hyperlink_headings()
+            .rewrite_source_links(root, md, files),
+    );
+    if let Some(highlight) = config.highlight.as_ref() {
+        events = Box::new(events.highlight_code(&highlight.syntaxes));
+    }
+    if !config.image_widths.is_empty() {
+        events = Box::new(events.responsive_images(&md.uri, images, &config.image_widths));
+    }
+
+    let mut res = String::new();
+    pulldown_cmark::html::push_html(&mut res, events);
+    res
+}
 
 #[derive(Clone)]
 enum MetaKey {
@@ -26,9 +61,21 @@ enum MetaKey {
     Category,
     Tags,
     Template,
+    WordCount,
+    ReadingTime,
+    SeriesPart,
+    SeriesLength,
     Meta(String),
 }
 
+/// Which posts are considered to be neighbours.
+#[derive(Clone)]
+enum Scope {
+    Directory,
+    Category,
+    Series,
+}
+
 #[derive(Clone)]
 enum PreprocessorRule {
     Contents,
@@ -42,6 +89,10 @@ enum PreprocessorRule {
         sort_by: Option<(MetaKey, bool)>,
         max_depth: Option<usize>,
         skip: Vec<String>,
+        /// (meta key, value it must have or contain)
+        filter: Vec<(MetaKey, String)>,
+        /// (meta key name, meta key) whose values are shown next to each item
+        show: Vec<(String, MetaKey)>,
     },
     Meta {
         key: String,
@@ -49,6 +100,14 @@ enum PreprocessorRule {
     Include {
         path: String,
     },
+    Neighbour {
+        /// Whether the next post is wanted (or the previous one otherwise).
+        next: bool,
+        /// Meta key to sort by, if not the default order (date, or reading order for series).
+        sort_by: Option<MetaKey>,
+        scope: Scope,
+    },
+    Series,
 }
 
 #[derive(Clone)]
@@ -76,10 +135,54 @@ impl MetaKey {
             Self::Tags
         } else if value == META_KEY_TEMPLATE {
             Self::Template
+        } else if value == META_KEY_WORD_COUNT {
+            Self::WordCount
+        } else if value == META_KEY_READING_TIME {
+            Self::ReadingTime
+        } else if value == META_KEY_SERIES_PART {
+            Self::SeriesPart
+        } else if value == META_KEY_SERIES_LENGTH {
+            Self::SeriesLength
         } else {
             Self::Meta(value)
         }
     }
+
+    /// The post's value for this key, typed so that it can be compared.
+    fn value(&self, post: &Post) -> Option<MetaValue> {
+        Some(match self {
+            Self::Title => MetaValue::String(post.title.clone()),
+            Self::CreationDate => date_value(post.date),
+            Self::ModifiedDate => date_value(post.updated),
+            Self::Category => MetaValue::String(post.category.clone()),
+            Self::Tags => {
+                MetaValue::List(post.tags.iter().cloned().map(MetaValue::String).collect())
+            }
+            Self::Template => MetaValue::String(post.template.as_ref()?.to_str()?.to_owned()),
+            Self::WordCount => MetaValue::Integer(post.word_count as i64),
+            Self::ReadingTime => MetaValue::Integer(post.reading_time as i64),
+            Self::SeriesPart => MetaValue::Integer(post.series_part?.0 as i64),
+            Self::SeriesLength => MetaValue::Integer(post.series_part?.1 as i64),
+            Self::Meta(key) => post.meta_value(key)?.clone(),
+        })
+    }
+
+    /// Compare the posts by their value for this key. Posts without it go first, as if it was the smallest value.
+    fn compare(&self, a: &Post, b: &Post) -> Ordering {
+        match (self.value(a), self.value(b)) {
+            (Some(a), Some(b)) => a.compare(&b),
+            (a, b) => a.is_some().cmp(&b.is_some()),
+        }
+    }
+}
+
+/// Dates at midnight are shown (and compared) as plain dates, since they most likely had no time.
+fn date_value(datetime: DateTime<FixedOffset>) -> MetaValue {
+    if datetime.time() == NaiveTime::MIN {
+        MetaValue::Date(datetime.date_naive())
+    } else {
+        MetaValue::DateTime(datetime)
+    }
 }
 
 impl PreprocessorRule {
@@ -108,6 +211,8 @@ impl PreprocessorRule {
                 let mut sort_by = None;
                 let mut max_depth = None;
                 let mut skip = Vec::new();
+                let mut filter = Vec::new();
+                let mut show = Vec::new();
                 while let Some(arg) = utils::parse_next_value(parsing) {
                     match arg.as_ref() {
                         "sort" => {
@@ -135,6 +240,20 @@ impl PreprocessorRule {
                             Some(uri) => skip.push(uri),
                             None => eprintln!("note: skip requires a relative uri"),
                         },
+                        "filter" => match (
+                            utils::parse_next_value(parsing),
+                            utils::parse_next_value(parsing),
+                        ) {
+                            (Some(key), Some(value)) => filter.push((MetaKey::new(key), value)),
+                            (key, value) => eprintln!(
+                                "note: filter requires key and value, but got: {:?}, {:?}",
+                                key, value
+                            ),
+                        },
+                        "show" => match utils::parse_next_value(parsing) {
+                            Some(key) => show.push((key.clone(), MetaKey::new(key))),
+                            None => eprintln!("note: show requires a meta key"),
+                        },
                         _ => eprintln!("note: unrecognized list argument: {}", arg),
                     }
                 }
@@ -144,6 +263,8 @@ impl PreprocessorRule {
                     sort_by,
                     max_depth,
                     skip,
+                    filter,
+                    show,
                 }
             }
             RULE_META => {
@@ -154,6 +275,34 @@ impl PreprocessorRule {
                 let path = utils::parse_next_value(parsing)?;
                 PreprocessorRule::Include { path }
             }
+            RULE_SERIES => PreprocessorRule::Series,
+            RULE_PREV | RULE_NEXT => {
+                let mut sort_by = None;
+                let mut scope = Scope::Directory;
+                while let Some(arg) = utils::parse_next_value(parsing) {
+                    match arg.as_ref() {
+                        "sort" => match utils::parse_next_value(parsing) {
+                            Some(key) => sort_by = Some(MetaKey::new(key)),
+                            None => eprintln!("note: sort requires a meta key"),
+                        },
+                        "in" => match utils::parse_next_value(parsing).as_deref() {
+                            Some("dir") => scope = Scope::Directory,
+                            Some("category") => scope = Scope::Category,
+                            Some("series") => scope = Scope::Series,
+                            other => eprintln!(
+                                "note: in requires dir, category or series, but got: {:?}",
+                                other
+                            ),
+                        },
+                        _ => eprintln!("note: unrecognized {} argument: {}", rule, arg),
+                    }
+                }
+                PreprocessorRule::Neighbour {
+                    next: rule == RULE_NEXT,
+                    sort_by,
+                    scope,
+                }
+            }
             _ => return None,
         })
     }
@@ -204,12 +353,30 @@ impl HtmlTemplate {
         Self { html, replacements }
     }
 
+    /// Paths referenced by the `LIST` and `INCLUDE` rules, along with the line and name of the rule.
+    pub fn referenced_paths(&self) -> Vec<(usize, &'static str, &str)> {
+        self.replacements
+            .iter()
+            .filter_map(|replacement| {
+                let (rule, path) = match &replacement.rule {
+                    PreprocessorRule::Listing { path, .. } => (RULE_LIST, path),
+                    PreprocessorRule::Include { path } => (RULE_INCLUDE, path),
+                    _ => return None,
+                };
+                let line = self.html[..replacement.range.start].matches('\n').count() + 1;
+                Some((line, rule, path.as_str()))
+            })
+            .collect()
+    }
+
     pub fn apply(
         &self,
+        config: &Config,
         root: &Path,
         md: &Post,
         files: &[Post],
-        css_files: &[String],
+        styles: &Styles,
+        images: &HashMap<String, Image>,
     ) -> io::Result<String> {
         let mut html = self.html.clone();
         let mut replacements = self.replacements.clone();
@@ -217,26 +384,8 @@ impl HtmlTemplate {
 
         for replacement in replacements.into_iter().rev() {
             let value = match replacement.rule {
-                PreprocessorRule::Contents => {
-                    let mut res = String::new();
-                    pulldown_cmark::html::push_html(
-                        &mut res,
-                        Parser::new_ext(&md.markdown, md::Options::all()).hyperlink_headings(),
-                    );
-                    res
-                }
-                PreprocessorRule::Css => {
-                    let mut res = String::new();
-                    for css in css_files {
-                        let parent = &css[..css.rfind('/').unwrap()];
-                        if md.uri.starts_with(parent) {
-                            res.push_str(r#"<link rel="stylesheet" type="text/css" href=""#);
-                            res.push_str(&utils::get_relative_uri(&md.uri, css));
-                            res.push_str("\">");
-                        }
-                    }
-                    res
-                }
+                PreprocessorRule::Contents => render_contents(config, root, md, files, images),
+                PreprocessorRule::Css => styles.html_for(&md.uri)?,
                 PreprocessorRule::Toc { depth: max_depth } => {
                     let mut res = String::new();
                     let mut cur_depth = 0;
@@ -279,6 +428,8 @@ impl HtmlTemplate {
                     sort_by,
                     max_depth,
                     skip,
+                    filter,
+                    show,
                 } => {
                     let path = utils::get_abs_path(root, &md.path, &path);
 
@@ -287,15 +438,7 @@ impl HtmlTemplate {
                     if let Some((key, asc)) = sort_by {
                         sorted_files = files.to_vec();
                         sorted_files.sort_by(|a, b| {
-                            let ordering = match &key {
-                                MetaKey::Title => a.title.cmp(&b.title),
-                                MetaKey::CreationDate => a.date.cmp(&b.date),
-                                MetaKey::ModifiedDate => a.updated.cmp(&b.updated),
-                                MetaKey::Category => a.category.cmp(&b.category),
-                                MetaKey::Tags => a.tags.cmp(&b.tags),
-                                MetaKey::Template => a.template.cmp(&b.template),
-                                MetaKey::Meta(key) => a.meta.get(key).cmp(&b.meta.get(key)),
-                            };
+                            let ordering = key.compare(a, b);
 
                             if asc {
                                 ordering
@@ -312,7 +455,8 @@ impl HtmlTemplate {
                         if file.path.starts_with(&path) {
                             let rel = utils::get_relative_uri(&md.uri, &file.uri);
                             if let Some(depth) = max_depth {
-                                if rel.matches('/').count() >= depth {
+                                // Pretty URLs end with a slash which doesn't add to their depth.
+                                if rel.trim_end_matches('/').matches('/').count() >= depth {
                                     continue 'files;
                                 }
                             }
@@ -323,18 +467,117 @@ impl HtmlTemplate {
                                 }
                             }
 
+                            for (key, value) in filter.iter() {
+                                if !key.value(file).is_some_and(|v| v.matches(value)) {
+                                    continue 'files;
+                                }
+                            }
+
                             res.push_str("<li><a href=\"");
                             res.push_str(&rel);
                             res.push_str("\">");
                             res.push_str(&file.title);
-                            res.push_str("</a></li>");
+                            res.push_str("</a>");
+                            for (name, key) in show.iter() {
+                                if let Some(value) = key.value(file) {
+                                    res.push_str(" <span class=\"");
+                                    md::escape::escape_html(&mut res, name).unwrap();
+                                    res.push_str("\">");
+                                    md::escape::escape_html(&mut res, &value.to_string()).unwrap();
+                                    res.push_str("</span>");
+                                }
+                            }
+                            res.push_str("</li>");
                         }
                     }
                     res.push_str("</ul>");
                     res
                 }
-                PreprocessorRule::Meta { key } => {
-                    md.meta.get(&key).cloned().unwrap_or_else(String::new)
+                PreprocessorRule::Meta { key } => match MetaKey::new(key.clone()) {
+                    // Computed from the post, so they're not found in its metadata.
+                    key @ (MetaKey::WordCount
+                    | MetaKey::ReadingTime
+                    | MetaKey::SeriesPart
+                    | MetaKey::SeriesLength) => {
+                        key.value(md).map(|v| v.to_string()).unwrap_or_default()
+                    }
+                    _ => md
+                        .meta_value(&key)
+                        .map(MetaValue::to_string)
+                        .unwrap_or_default(),
+                },
+                PreprocessorRule::Neighbour {
+                    next,
+                    sort_by,
+                    scope,
+                } => {
+                    let neighbours = match (scope, sort_by) {
+                        (Scope::Series, None) => series::series_posts(md, files),
+                        (scope, sort_by) => {
+                            let sort_by = sort_by.unwrap_or(MetaKey::CreationDate);
+                            let mut neighbours = files
+                                .iter()
+                                .filter(|file| match scope {
+                                    Scope::Directory => file.path.parent() == md.path.parent(),
+                                    Scope::Category => file.category == md.category,
+                                    Scope::Series => {
+                                        file.series.is_some() && file.series == md.series
+                                    }
+                                })
+                                .collect::<Vec<_>>();
+                            // Ties are broken by path so that every post agrees on who its neighbours are.
+                            neighbours.sort_by(|a, b| {
+                                sort_by.compare(a, b).then_with(|| a.path.cmp(&b.path))
+                            });
+                            neighbours
+                        }
+                    };
+
+                    let index = neighbours.iter().position(|file| file.path == md.path);
+                    let neighbour = match index {
+                        Some(i) if next => neighbours.get(i + 1),
+                        Some(i) => i.checked_sub(1).and_then(|i| neighbours.get(i)),
+                        None => None,
+                    };
+
+                    match neighbour {
+                        Some(file) => {
+                            let mut res = String::new();
+                            res.push_str("<a href=\"");
+                            res.push_str(&utils::get_relative_uri(&md.uri, &file.uri));
+                            res.push_str(if next {
+                                "\" rel=\"next\">"
+                            } else {
+                                "\" rel=\"prev\">"
+                            });
+                            res.push_str(&file.title);
+                            res.push_str("</a>");
+                            res
+                        }
+                        None => String::new(),
+                    }
+                }
+                PreprocessorRule::Series => {
+                    let mut res = String::new();
+                    let parts = series::series_posts(md, files);
+                    if !parts.is_empty() {
+                        res.push_str("<ol>");
+                        for part in parts {
+                            if part.path == md.path {
+                                res.push_str("<li><strong>");
+                                res.push_str(&part.title);
+                                res.push_str("</strong></li>");
+                            } else {
+                                res.push_str("<li><a href=\"");
+                                res.push_str(&utils::get_relative_uri(&md.uri, &part.uri));
+                                res.push_str("\">");
+                                res.push_str(&part.title);
+                                res.push_str("</a></li>");
+                            }
+                        }
+                        res.push_str("</ol>");
+                    }
+                    res
                 }
                 PreprocessorRule::Include { path } => {
                     let path = utils::get_abs_path(root, &md.path, &path);
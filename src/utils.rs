@@ -1,4 +1,22 @@
-use std::path::{Path, PathBuf};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+/// An HTML start tag, with the byte ranges of its attribute values within the HTML it was found in.
+pub struct HtmlTag<'a> {
+    pub name: &'a str,
+    pub attrs: Vec<(&'a str, Range<usize>)>,
+    /// Byte range of the contents of `script` and `style` elements, which aren't HTML.
+    pub text: Option<Range<usize>>,
+}
+
+impl HtmlTag<'_> {
+    pub fn attr(&self, key: &str) -> Option<Range<usize>> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, range)| range.clone())
+    }
+}
 
 /// Parses the next value in the given string. `value` is left at the next value. Parsed value is returned.
 pub fn parse_next_value(string: &mut &str) -> Option<String> {
@@ -76,6 +94,210 @@ pub fn get_abs_path(root: &Path, path: &Path, value: &str) -> PathBuf {
     }
 }
 
+/// Whether the path has the given extension, ignoring case.
+pub fn has_extension(path: &Path, ext: &str) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case(ext))
+        .unwrap_or(false)
+}
+
+/// Whether the URI points outside of the site (it has a scheme such as `https:` or is protocol-relative).
+pub fn is_external_uri(uri: &str) -> bool {
+    if uri.starts_with("//") {
+        return true;
+    }
+    match uri.find([':', '/', '?', '#']) {
+        Some(i) => {
+            i != 0
+                && uri.as_bytes()[i] == b':'
+                && uri[..i]
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+        }
+        None => false,
+    }
+}
+
+/// Split the URI into its path and the query and fragment suffix (which starts with `?` or `#`, if any).
+pub fn split_uri_suffix(uri: &str) -> (&str, &str) {
+    uri.split_at(uri.find(['?', '#']).unwrap_or(uri.len()))
+}
+
+/// Decode the percent-encoded bytes of an URI (as in `my%20file.png`), leaving it as-is if they aren't valid UTF-8.
+pub fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes
+            .get(index + 1..index + 3)
+            .filter(|hex| bytes[index] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match byte {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).unwrap_or_else(|_| uri.to_owned())
+}
+
+/// Resolve a local URI found in the file at `path` into the file it refers to, with `..` removed.
+///
+/// Returns `None` for external URIs and for those made up of only a query or fragment.
+pub fn resolve_uri(root: &Path, path: &Path, uri: &str) -> Option<PathBuf> {
+    let (uri_path, _) = split_uri_suffix(uri);
+    if uri_path.is_empty() || is_external_uri(uri) {
+        return None;
+    }
+
+    let mut resolved = PathBuf::new();
+    for component in get_abs_path(root, path, uri_path).components() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            c => resolved.push(c),
+        }
+    }
+    Some(resolved)
+}
+
+/// Attributes of HTML tags which may contain URIs.
+const HTML_URI_ATTRIBUTES: [&str; 4] = ["href", "src", "srcset", "poster"];
+
+/// Replace the URIs in the attributes of the HTML for which `map` returns a new value.
+///
+/// Every candidate in a `srcset` is mapped separately, keeping its descriptor.
+pub fn map_html_uris<F: FnMut(&str) -> Option<String>>(html: &str, mut map: F) -> String {
+    let mut replacements = Vec::new();
+    for tag in parse_html_tags(html) {
+        for (key, range) in tag.attrs {
+            if !HTML_URI_ATTRIBUTES
+                .iter()
+                .any(|a| key.eq_ignore_ascii_case(a))
+            {
+                continue;
+            }
+
+            let value = &html[range.clone()];
+            let rewritten = if key.eq_ignore_ascii_case("srcset") {
+                // Every candidate is made up of an URI optionally followed by a descriptor.
+                let mut changed = false;
+                let candidates = value
+                    .split(',')
+                    .map(|candidate| {
+                        let candidate = candidate.trim();
+                        let (candidate_uri, descriptor) =
+                            candidate.split_at(candidate.find(' ').unwrap_or(candidate.len()));
+                        match map(candidate_uri) {
+                            Some(new) => {
+                                changed = true;
+                                format!("{}{}", new, descriptor)
+                            }
+                            None => candidate.to_owned(),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                Some(candidates.join(", ")).filter(|_| changed)
+            } else {
+                map(value)
+            };
+
+            if let Some(rewritten) = rewritten {
+                replacements.push((range, rewritten));
+            }
+        }
+    }
+
+    let mut html = html.to_owned();
+    for (range, rewritten) in replacements.into_iter().rev() {
+        html.replace_range(range, &rewritten);
+    }
+    html
+}
+
+/// Turn an URI found in the file at `base` into an absolute URL under `url`, the URL where the root
+/// of the site is hosted (which may be a subpath, as in `https://user.github.io/project/`).
+///
+/// External URIs are returned as-is.
+pub fn absolute_url(url: &str, base: &str, uri: &str) -> String {
+    if is_external_uri(uri) {
+        return uri.to_owned();
+    }
+
+    let (path, suffix) = split_uri_suffix(uri);
+    let path = if path.is_empty() {
+        base.to_owned()
+    } else {
+        join_uri(base, path)
+    };
+
+    // The path is absolute within the site, so it brings its own leading slash.
+    format!("{}{}{}", url.trim_end_matches('/'), path, suffix)
+}
+
+/// Join a relative URI to the absolute URI of the file it was found in, with `.` and `..` removed.
+pub fn join_uri(base: &str, uri: &str) -> String {
+    let mut segments = if uri.starts_with('/') {
+        Vec::new()
+    } else {
+        base.split('/').collect::<Vec<_>>()
+    };
+    // The last segment is the file name (or empty, if the base was a directory).
+    segments.pop();
+
+    for segment in uri.trim_start_matches('/').split('/') {
+        match segment {
+            "." => {}
+            ".." => {
+                if segments.len() > 1 {
+                    segments.pop();
+                }
+            }
+            s => segments.push(s),
+        }
+    }
+
+    let mut result = segments.join("/");
+    if !result.starts_with('/') {
+        result.insert(0, '/');
+    }
+    result
+}
+
+/// The directory part of an URI, up to and including the last slash.
+pub fn uri_dir(uri: &str) -> &str {
+    &uri[..uri.rfind('/').map(|i| i + 1).unwrap_or(0)]
+}
+
+/// Insert some text right before the extension of the file name (or URI), if any.
+pub fn insert_before_extension(name: &str, infix: &str) -> String {
+    let file_start = name.rfind('/').map(|i| i + 1).unwrap_or(0);
+    let ext_start = name[file_start..]
+        .rfind('.')
+        .filter(|i| *i != 0)
+        .map(|i| file_start + i)
+        .unwrap_or_else(|| name.len());
+    format!("{}{}{}", &name[..ext_start], infix, &name[ext_start..])
+}
+
+/// Insert the width of a downscaled image variant before the extension of the name (or URI) of the original.
+pub fn variant_name(name: &str, width: u32) -> String {
+    insert_before_extension(name, &format!("-{}w", width))
+}
+
+/// Insert the hash of the content before the extension of the name (or URI) of the file.
+pub fn fingerprint_name(name: &str, content: &[u8]) -> String {
+    insert_before_extension(name, &format!(".{}", content_hash(content)))
+}
+
 /// Replace's `path`'s `source` root with `destination`. Panics if `path` does not start with `source`.
 ///
 /// Rust's path (and `OsString`) manipulation is pretty lacking, so the method falls back to `String`.
@@ -124,9 +346,153 @@ pub fn get_relative_uri(relative_to: &str, uri: &str) -> String {
     uri[last_shared_slash + 1..]
         .iter()
         .for_each(|c| result.push(*c as _));
+    if result.is_empty() {
+        // Both point to the same directory (as is the case with trailing slashes).
+        result.push_str("./");
+    }
     result
 }
 
+/// Elements whose contents are raw text rather than HTML.
+const RAW_TEXT_TAGS: [&str; 2] = ["script", "style"];
+
+/// Find the tags in the HTML along with their attributes, skipping comments and the contents of scripts and styles.
+///
+/// Attribute values may be double-quoted, single-quoted or unquoted (as is the case after minifying).
+pub fn parse_html_tags(html: &str) -> Vec<HtmlTag<'_>> {
+    let bytes = html.as_bytes();
+    let is_name_end = |b: u8| b.is_ascii_whitespace() || b == b'=' || b == b'>' || b == b'/';
+
+    let mut tags = Vec::new();
+    let mut index = 0;
+    while let Some(i) = html[index..].find('<') {
+        index += i + 1;
+        if html[index..].starts_with("!--") {
+            index = match html[index..].find("-->") {
+                Some(i) => index + i,
+                None => break,
+            };
+            continue;
+        }
+
+        let name_start = index;
+        while index < bytes.len() && !is_name_end(bytes[index]) {
+            index += 1;
+        }
+        let name = &html[name_start..index];
+
+        let mut attrs = Vec::new();
+        while index < bytes.len() && bytes[index] != b'>' {
+            if bytes[index].is_ascii_whitespace() || bytes[index] == b'/' {
+                index += 1;
+                continue;
+            }
+
+            let key_start = index;
+            while index < bytes.len() && !is_name_end(bytes[index]) {
+                index += 1;
+            }
+            let key = &html[key_start..index];
+            if key.is_empty() {
+                // Stray `=`, skip it so that we make progress.
+                index += 1;
+                continue;
+            }
+
+            if bytes.get(index) != Some(&b'=') {
+                attrs.push((key, index..index));
+                continue;
+            }
+            index += 1;
+
+            let value = match bytes.get(index) {
+                Some(&quote) if quote == b'"' || quote == b'\'' => {
+                    let value_start = index + 1;
+                    let value_end = html[value_start..]
+                        .find(quote as char)
+                        .map(|i| value_start + i)
+                        .unwrap_or(bytes.len());
+                    index = (value_end + 1).min(bytes.len());
+                    value_start..value_end
+                }
+                _ => {
+                    let value_start = index;
+                    while index < bytes.len()
+                        && !bytes[index].is_ascii_whitespace()
+                        && bytes[index] != b'>'
+                    {
+                        index += 1;
+                    }
+                    value_start..index
+                }
+            };
+            attrs.push((key, value));
+        }
+
+        // Whatever is inside scripts and styles may look like tags, but isn't.
+        let mut text = None;
+        if RAW_TEXT_TAGS.iter().any(|t| name.eq_ignore_ascii_case(t)) && index < bytes.len() {
+            let text_start = index + 1;
+            let closing = format!("</{}", name.to_ascii_lowercase());
+            let text_end = html[text_start..]
+                .to_ascii_lowercase()
+                .find(&closing)
+                .map(|i| text_start + i)
+                .unwrap_or(bytes.len());
+            text = Some(text_start..text_end);
+            index = text_end;
+        }
+
+        tags.push(HtmlTag { name, attrs, text });
+    }
+    tags
+}
+
+/// Hash the content of a file into a short hexadecimal string suitable for file names.
+///
+/// This uses 64-bit FNV-1a, which unlike `DefaultHasher` is guaranteed to be stable across builds.
+pub fn content_hash(content: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in content {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Format a size in bytes using the largest binary unit that keeps it above one.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Parse a size in bytes, optionally followed by a binary unit (`K`, `KiB`, `M`, `MiB`…).
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let digits = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let value = size[..digits].parse::<u64>().ok()?;
+    let multiplier = match size[digits..].trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    value.checked_mul(multiplier)
+}
+
 pub fn generate_heading_id(heading: &str) -> String {
     let lowercase = heading.to_lowercase();
     let mut result = String::with_capacity(lowercase.len());
@@ -146,6 +512,179 @@ pub fn generate_heading_id(heading: &str) -> String {
 mod tests {
     use super::*;
 
+    mod uri {
+        use super::*;
+
+        #[test]
+        fn external() {
+            assert!(is_external_uri("https://example.com/a.png"));
+            assert!(is_external_uri("mailto:someone@example.com"));
+            assert!(is_external_uri("//example.com/a.png"));
+            assert!(!is_external_uri("a.png"));
+            assert!(!is_external_uri("/img/a.png"));
+            assert!(!is_external_uri("img/a:b.png"));
+            assert!(!is_external_uri("#heading"));
+        }
+
+        #[test]
+        fn suffix() {
+            assert_eq!(split_uri_suffix("a.md"), ("a.md", ""));
+            assert_eq!(split_uri_suffix("a.md#top"), ("a.md", "#top"));
+            assert_eq!(split_uri_suffix("a.md?q=1#top"), ("a.md", "?q=1#top"));
+            assert_eq!(split_uri_suffix("#top"), ("", "#top"));
+        }
+
+        #[test]
+        fn decode() {
+            assert_eq!(percent_decode("my%20file.png"), "my file.png");
+            assert_eq!(percent_decode("caf%C3%A9.md#top"), "café.md#top");
+            assert_eq!(percent_decode("100%.png"), "100%.png");
+            assert_eq!(percent_decode("a%2"), "a%2");
+            assert_eq!(percent_decode("%FF.png"), "%FF.png");
+        }
+
+        #[test]
+        fn resolve() {
+            let root = Path::new("/blog/content");
+            let path = Path::new("/blog/content/posts/hello.md");
+            assert_eq!(
+                resolve_uri(root, path, "../img/a.png?v=1"),
+                Some(PathBuf::from("/blog/content/img/a.png"))
+            );
+            assert_eq!(
+                resolve_uri(root, path, "/img/./a.png"),
+                Some(PathBuf::from("/blog/content/img/a.png"))
+            );
+            assert_eq!(resolve_uri(root, path, "#top"), None);
+            assert_eq!(resolve_uri(root, path, "https://example.com"), None);
+        }
+
+        #[test]
+        fn relative() {
+            assert_eq!(get_relative_uri("/blog/a.html", "/blog/b.html"), "b.html");
+            assert_eq!(get_relative_uri("/blog/a.html", "/site.css"), "../site.css");
+            assert_eq!(get_relative_uri("/blog/a/", "/blog/b/"), "../b/");
+            assert_eq!(get_relative_uri("/blog/a/", "/blog/"), "../");
+            assert_eq!(get_relative_uri("/blog/", "/blog/a/"), "a/");
+            assert_eq!(get_relative_uri("/blog/a/", "/blog/a/"), "./");
+        }
+
+        #[test]
+        fn absolute() {
+            let url = "https://example.com/";
+            assert_eq!(
+                absolute_url(url, "/blog/a.html", "../img/b.png"),
+                "https://example.com/img/b.png"
+            );
+            assert_eq!(
+                absolute_url(url, "/blog/a.html", "#top"),
+                "https://example.com/blog/a.html#top"
+            );
+            assert_eq!(
+                absolute_url("https://example.com", "/a/", "/b.html?x"),
+                "https://example.com/b.html?x"
+            );
+            assert_eq!(absolute_url(url, "/a.html", "mailto:a@b.c"), "mailto:a@b.c");
+
+            let url = "https://user.github.io/project/";
+            assert_eq!(
+                absolute_url(url, "/blog/a.html", "b.html"),
+                "https://user.github.io/project/blog/b.html"
+            );
+            assert_eq!(
+                absolute_url(url, "/blog/a.html", "../"),
+                "https://user.github.io/project/"
+            );
+        }
+
+        #[test]
+        fn join() {
+            assert_eq!(join_uri("/blog/a.css", "img/b.png"), "/blog/img/b.png");
+            assert_eq!(join_uri("/blog/a.css", "../img/b.png"), "/img/b.png");
+            assert_eq!(join_uri("/blog/a.css", "./../../b.png"), "/b.png");
+            assert_eq!(join_uri("/blog/a.css", "/img/b.png"), "/img/b.png");
+            assert_eq!(join_uri("/blog/", "b/"), "/blog/b/");
+        }
+
+        #[test]
+        fn variant() {
+            assert_eq!(variant_name("img/a.png", 480), "img/a-480w.png");
+            assert_eq!(variant_name("img.d/a", 480), "img.d/a-480w");
+            assert_eq!(variant_name(".hidden", 480), ".hidden-480w");
+        }
+
+        #[test]
+        fn fingerprint() {
+            assert_eq!(
+                fingerprint_name("/css/site.css", b""),
+                "/css/site.cbf29ce484222325.css"
+            );
+        }
+    }
+
+    mod html {
+        use super::*;
+
+        #[test]
+        fn tags() {
+            let html =
+                "<!-- <a href=no> --><link rel=stylesheet href='a.css'><img alt src=\"b.png\"/>";
+            let tags = parse_html_tags(html);
+            assert_eq!(tags.len(), 2);
+            assert_eq!(tags[0].name, "link");
+            assert_eq!(&html[tags[0].attr("rel").unwrap()], "stylesheet");
+            assert_eq!(&html[tags[0].attr("href").unwrap()], "a.css");
+            assert_eq!(tags[1].name, "img");
+            assert_eq!(&html[tags[1].attr("alt").unwrap()], "");
+            assert_eq!(&html[tags[1].attr("src").unwrap()], "b.png");
+        }
+
+        #[test]
+        fn raw_text() {
+            let html = "<script>if (a<b) { x = '<img src=no>'; }</script><STYLE>a{}</STYLE><img src=a.png>";
+            let tags = parse_html_tags(html);
+            let names = tags.iter().map(|tag| tag.name).collect::<Vec<_>>();
+            // Closing tags have no name.
+            assert_eq!(names, ["script", "", "STYLE", "", "img"]);
+            assert_eq!(
+                &html[tags[0].text.clone().unwrap()],
+                "if (a<b) { x = '<img src=no>'; }"
+            );
+            assert_eq!(&html[tags[2].text.clone().unwrap()], "a{}");
+            assert_eq!(tags[4].text, None);
+        }
+    }
+
+    mod hash {
+        use super::*;
+
+        #[test]
+        fn stable() {
+            assert_eq!(content_hash(b""), "cbf29ce484222325");
+            assert_eq!(content_hash(b"a"), "af63dc4c8601ec8c");
+        }
+    }
+
+    mod size {
+        use super::*;
+
+        #[test]
+        fn format() {
+            assert_eq!(format_size(512), "512 B");
+            assert_eq!(format_size(1536), "1.5 KiB");
+            assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+        }
+
+        #[test]
+        fn parse() {
+            assert_eq!(parse_size("512"), Some(512));
+            assert_eq!(parse_size("100K"), Some(100 * 1024));
+            assert_eq!(parse_size("2 MiB"), Some(2 * 1024 * 1024));
+            assert_eq!(parse_size("big"), None);
+            assert_eq!(parse_size("1 parsec"), None);
+        }
+    }
+
     mod parse_value {
         use super::*;
 
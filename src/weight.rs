@@ -0,0 +1,201 @@
+use crate::config::{Config, STYLE_FILE_EXT};
+use crate::{style, utils};
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Tags whose `src` is downloaded along with the page.
+const SRC_TAGS: [&str; 7] = [
+    "img", "script", "iframe", "video", "audio", "source", "embed",
+];
+/// Values of `rel` in `link` tags whose `href` is downloaded along with the page.
+const LINK_RELS: [&str; 3] = ["stylesheet", "icon", "preload"];
+
+/// Total transfer weight of a generated page.
+pub struct PageWeight {
+    /// Generated file path.
+    pub path: PathBuf,
+    /// Size of the HTML itself.
+    pub html: u64,
+    /// Size of every resource the HTML links to (and which could be found in the output).
+    pub resources: u64,
+    /// Amount of resources which were found.
+    pub resource_count: usize,
+}
+
+impl PageWeight {
+    pub fn total(&self) -> u64 {
+        self.html + self.resources
+    }
+}
+
+/// Measure the weight of a generated page at `path` by resolving its resources from the `destination`.
+///
+/// The resources referenced from its CSS (such as fonts and background images) are counted too.
+pub fn measure_page(destination: &Path, path: &Path, html: &str) -> PageWeight {
+    let mut seen = HashSet::new();
+    let mut resources = 0;
+    let mut pending = linked_resources(html)
+        .into_iter()
+        .map(|candidates| (path.to_path_buf(), candidates))
+        .collect::<Vec<_>>();
+    while let Some((base, candidates)) = pending.pop() {
+        let candidates = candidates
+            .iter()
+            .filter_map(|uri| utils::resolve_uri(destination, &base, uri))
+            .collect::<Vec<_>>();
+        if candidates.iter().any(|resource| seen.contains(resource)) {
+            continue;
+        }
+
+        // Only one of the candidates is downloaded, so assume the worst.
+        let largest = candidates
+            .into_iter()
+            .filter_map(|resource| Some((fs::metadata(&resource).ok()?.len(), resource)))
+            .max();
+        if let Some((size, resource)) = largest {
+            if utils::has_extension(&resource, STYLE_FILE_EXT) {
+                if let Ok(css) = fs::read_to_string(&resource) {
+                    pending.extend(
+                        css_resources(&css)
+                            .into_iter()
+                            .map(|uri| (resource.clone(), vec![uri])),
+                    );
+                }
+            }
+            resources += size;
+            seen.insert(resource);
+        }
+    }
+
+    PageWeight {
+        path: path.to_path_buf(),
+        html: html.len() as u64,
+        resources,
+        resource_count: seen.len(),
+    }
+}
+
+/// Print the report of page weights if requested, and check them against the budget.
+pub fn check_budget(
+    config: &Config,
+    destination: &Path,
+    mut weights: Vec<PageWeight>,
+) -> io::Result<()> {
+    weights.sort_by_key(|w| std::cmp::Reverse(w.total()));
+
+    if config.weight_report {
+        for weight in weights.iter() {
+            println!(
+                "{:>12}  {} (html {}, {} resources {})",
+                utils::format_size(weight.total()),
+                utils::path_to_uri(destination, &weight.path),
+                utils::format_size(weight.html),
+                weight.resource_count,
+                utils::format_size(weight.resources),
+            );
+        }
+    }
+
+    let budget = match config.weight_budget {
+        Some(budget) => budget,
+        None => return Ok(()),
+    };
+
+    let over_budget = weights.iter().filter(|w| w.total() > budget).count();
+    for weight in weights.iter().filter(|w| w.total() > budget) {
+        eprintln!(
+            "warning: page weighs {} which is over the budget of {}: {:?}",
+            utils::format_size(weight.total()),
+            utils::format_size(budget),
+            weight.path
+        );
+    }
+
+    if over_budget != 0 && config.fail_over_budget {
+        Err(io::Error::other(format!(
+            "{} page(s) over the weight budget",
+            over_budget
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Find the URIs of the resources that browsers would download along with the HTML.
+///
+/// Every item holds the candidates for a single resource, as is the case with `srcset`, of which only one is downloaded.
+fn linked_resources(html: &str) -> Vec<Vec<String>> {
+    let mut result = Vec::new();
+    for tag in utils::parse_html_tags(html) {
+        let attr = |key: &str| tag.attr(key).map(|range| html[range].replace("&amp;", "&"));
+
+        if tag.name.eq_ignore_ascii_case("link") {
+            let rel = attr("rel").unwrap_or_default().to_ascii_lowercase();
+            if rel.split_whitespace().any(|r| LINK_RELS.contains(&r)) {
+                result.extend(attr("href").map(|href| vec![href]));
+            }
+        } else if SRC_TAGS.iter().any(|t| tag.name.eq_ignore_ascii_case(t)) {
+            let mut candidates = attr("src").into_iter().collect::<Vec<_>>();
+            if let Some(srcset) = attr("srcset") {
+                // Every candidate is made up of an URI optionally followed by a descriptor.
+                candidates.extend(
+                    srcset
+                        .split(',')
+                        .filter_map(|candidate| candidate.split_whitespace().next())
+                        .map(str::to_owned),
+                );
+            }
+            if !candidates.is_empty() {
+                result.push(candidates);
+            }
+        }
+
+        // Inlined CSS may reference resources too.
+        if let (Some(text), true) = (tag.text.clone(), tag.name.eq_ignore_ascii_case("style")) {
+            result.extend(css_resources(&html[text]).into_iter().map(|uri| vec![uri]));
+        }
+    }
+    result
+}
+
+/// Find the URIs of the `url()`s and `@import`s in the CSS.
+fn css_resources(css: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    style::map_urls(css, |url| {
+        result.push(url.to_owned());
+        None
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resources() {
+        let html = concat!(
+            "<!-- <img src=commented.png> -->",
+            "<link rel=\"stylesheet preload\" href=a.css?x=1&amp;y=2><link rel=canonical href=b.html>",
+            "<script src='c.js'>document.write('<img src=d.png>');</script>",
+            "<img src=e.png srcset=\"e-480w.png 480w, e.png 960w\" alt=\"<e>\">",
+            "<style>body { background: url(\"f.png\"); }</style>",
+        );
+        assert_eq!(
+            linked_resources(html),
+            vec![
+                vec!["a.css?x=1&y=2".to_owned()],
+                vec!["c.js".to_owned()],
+                vec![
+                    "e.png".to_owned(),
+                    "e-480w.png".to_owned(),
+                    "e.png".to_owned()
+                ],
+                vec!["f.png".to_owned()],
+            ]
+        );
+    }
+}